@@ -0,0 +1,116 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::Instant,
+};
+
+use log::*;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    errors::Error, game::Game, publish_lobby_event, GameId, InviteCode, CONFIG, LOBBY, METRICS,
+    STATE,
+};
+
+enum Command {
+    Run(Box<dyn FnOnce(&mut Game) + Send>),
+    Close,
+}
+
+/// A handle to a game running on its own task. The task owns the game and runs the commands sent
+/// to it one at a time, in the order they were sent, so nothing else ever needs to lock it.
+#[derive(Clone)]
+pub(crate) struct GameHandle {
+    sender: mpsc::UnboundedSender<Command>,
+    pub invite_code: InviteCode,
+}
+impl GameHandle {
+    /// Spawns the task for a game, lists it in the lobby and returns a handle to it. The task
+    /// exits once it's closed or every handle has been dropped. If a command panics, the game
+    /// can't be trusted any more, so it's removed as if it had been closed.
+    pub fn spawn(game_id: GameId, mut game: Game) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let invite_code = game.invite_code.clone();
+
+        tokio::spawn(async move {
+            METRICS.add_games(1);
+            let mut player_count = 0;
+            update_listing(&game_id, &game);
+            while let Some(command) = receiver.recv().await {
+                let version = game.get_version();
+                match command {
+                    Command::Run(f) => {
+                        if panic::catch_unwind(AssertUnwindSafe(|| f(&mut game))).is_err() {
+                            error!("Command for game {:?} panicked, removing it", game_id);
+                            STATE.remove_game(&game_id);
+                            break;
+                        }
+                    }
+                    Command::Close => break,
+                }
+                if game.get_version() == version {
+                    continue;
+                }
+
+                // Keep the lobby's copy current, so listing games never has to wait on this task.
+                update_listing(&game_id, &game);
+
+                let new_player_count = game.get_player_names().len() as i64;
+                METRICS.add_players(new_player_count - player_count);
+                player_count = new_player_count;
+            }
+
+            if let Some(event) = LOBBY.remove(&game_id) {
+                publish_lobby_event(event);
+            }
+            METRICS.add_players(-player_count);
+            METRICS.add_games(-1);
+            debug!("Game task for {:?} finished", game_id);
+        });
+
+        GameHandle {
+            sender,
+            invite_code,
+        }
+    }
+
+    /// Stops the game's task once it has run the commands already sent to it, and takes the game
+    /// out of the lobby. Anything sent after this fails with `Error::UnknownGame`.
+    pub fn close(&self) {
+        let _ = self.sender.send(Command::Close);
+    }
+
+    /// Runs `f` on the game's task and waits for its result. Fails with `Error::UnknownGame` if the
+    /// task is gone (or `f` panicked), or `Error::Timeout` if it doesn't get to the command in
+    /// time. A command that times out while still queued is dropped, but one the task had already
+    /// started on runs to the end, so its changes may still happen.
+    pub async fn run<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Game) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let sent_at = Instant::now();
+        self.sender
+            .send(Command::Run(Box::new(move |game: &mut Game| {
+                METRICS.record_command_wait(sent_at.elapsed());
+                // The caller already gave up waiting
+                if result_sender.is_closed() {
+                    return;
+                }
+                let _ = result_sender.send(f(game));
+            })))
+            .map_err(|_| Error::UnknownGame)?;
+
+        let timeout = CONFIG.get().unwrap().limits.operation_timeout;
+        match tokio::time::timeout(timeout, result_receiver).await {
+            Ok(result) => result.map_err(|_| Error::UnknownGame),
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+}
+
+fn update_listing(game_id: &GameId, game: &Game) {
+    if let Some(event) = LOBBY.update(game_id, game.time_started, game.serialize_listing()) {
+        publish_lobby_event(event);
+    }
+}
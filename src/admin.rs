@@ -0,0 +1,362 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use log::*;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use wamp_async::{WampArgs, WampError, WampKwArgs, WampPayloadValue};
+
+use crate::{
+    data,
+    errors::Error,
+    publish_notice,
+    server::{get_opt_str_parse, get_str, get_uuid, publish_kick_notice},
+    GameId, PlayerId, AVATAR_MANAGER, CONFIG, JEOPARDY_DATA, METRICS, PROFILE_MANAGER, STARTED_AT,
+    STATE,
+};
+
+pub const MIN_TOKEN_LENGTH: usize = 16;
+const DEFAULT_AVATAR_MIN_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Who may call the admin RPCs. Without a token, nobody can.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub token: Option<String>,
+}
+// The config gets logged at startup, so keep the token out of it.
+impl fmt::Debug for AdminConfig {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("AdminConfig")
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+fn tokens_match(given: &str, expected: &str) -> bool {
+    // Comparing digests rather than the tokens themselves doesn't give away how much of a guess
+    // was right.
+    Sha256::digest(given.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+/// Checks the admin token every admin call has to carry.
+fn check_token(kwargs: &WampKwArgs) -> Result<(), Error> {
+    let expected = CONFIG
+        .get()
+        .unwrap()
+        .admin
+        .token
+        .as_deref()
+        .ok_or(Error::NotAllowed)?;
+    let given = get_str(kwargs.get("admin_token").ok_or(Error::BadArgument)?)?;
+
+    if tokens_match(given, expected) {
+        Ok(())
+    } else {
+        warn!("Rejected an admin call with the wrong token");
+        Err(Error::BadCredentials)
+    }
+}
+
+/// List every game, including private ones, with details only operators get to see
+pub async fn list_games(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("admin::list_games");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    check_token(&kwargs)?;
+
+    let mut games = Vec::new();
+    for (game_id, handle) in STATE.get_handles() {
+        let summary_game_id = game_id.clone();
+        match handle
+            .run(move |game| {
+                (
+                    game.time_started,
+                    game.serialize_admin_summary(&summary_game_id),
+                )
+            })
+            .await
+        {
+            Ok(game) => games.push(game),
+            // It may have ended since the handles were collected.
+            Err(err) => warn!("Could not get game {:?}: {:?}", game_id, err),
+        }
+    }
+    games.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut result = WampKwArgs::new();
+    result.insert(
+        "games".into(),
+        WampPayloadValue::Array(
+            games
+                .into_iter()
+                .map(|(_, summary)| WampPayloadValue::Object(summary))
+                .collect(),
+        ),
+    );
+    Ok((None, Some(result)))
+}
+
+/// End a game as if its moderator had, archiving it
+pub async fn end_game(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("admin::end_game");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    check_token(&kwargs)?;
+    let game_id = GameId(get_uuid(kwargs.get("game_id").ok_or(Error::BadArgument)?)?);
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if game.is_ended {
+                return Err(Error::InvalidStateForOperation);
+            }
+
+            publish_notice(&game.notification_channel, "EndedByOperator", None);
+            game.end();
+            Ok(())
+        })
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Remove a player from any game, optionally banning them from rejoining it
+pub async fn kick(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("admin::kick");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    check_token(&kwargs)?;
+    let game_id = GameId(get_uuid(kwargs.get("game_id").ok_or(Error::BadArgument)?)?);
+    let target = PlayerId(get_uuid(kwargs.get("target").ok_or(Error::BadArgument)?)?);
+    let ban: bool = get_opt_str_parse(kwargs.get("ban"))?.unwrap_or(false);
+    let reason = kwargs.get("reason").map(get_str).transpose()?;
+
+    let kicked = target.clone();
+    let notification_channel = STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            game.kick_player(kicked, ban)?;
+            Ok(game.notification_channel.clone())
+        })
+        .await??;
+
+    publish_kick_notice(notification_channel, &target, ban, reason);
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Load the clue dataset again from the configured database path. Games already under way keep
+/// the boards they have; new boards are dealt from the new data.
+pub async fn reload_data(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("admin::reload_data");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    check_token(&kwargs)?;
+
+    let path = CONFIG.get().unwrap().paths.database.clone();
+    let start = Instant::now();
+    // Loading panics on a missing or malformed file, which only takes down this blocking task.
+    let jeopardy_data = tokio::task::spawn_blocking(move || data::load(path))
+        .await
+        .map_err(|err| {
+            warn!("Failed to reload the dataset: {}", err);
+            Error::DatasetLoad
+        })?;
+    let time_taken = start.elapsed();
+
+    // Boards are dealt at random from whatever's loaded, so there has to be something to deal.
+    if jeopardy_data.categories.is_empty() || jeopardy_data.final_jeopardy_questions.is_empty() {
+        warn!("Not reloading an empty dataset");
+        return Err(Error::DatasetLoad.into());
+    }
+
+    info!(
+        "Reloaded {} categories and {} final jeopardy questions in {} ms",
+        jeopardy_data.categories.len(),
+        jeopardy_data.final_jeopardy_questions.len(),
+        time_taken.as_millis(),
+    );
+    METRICS.set_dataset_load_time(time_taken);
+
+    let result = serialize_dataset_stats(&jeopardy_data);
+    *JEOPARDY_DATA.write() = Arc::new(jeopardy_data);
+    Ok((None, Some(result)))
+}
+
+/// Delete saved avatars that no profile and nobody in a running game is using. Only avatars
+/// older than `min_age_secs` (a week by default) are deleted, and with `dry_run` nothing is.
+/// Archived games keep their avatar URLs, so reviewing old games may show missing images.
+pub async fn purge_avatars(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("admin::purge_avatars");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    check_token(&kwargs)?;
+    let min_age = get_opt_str_parse(kwargs.get("min_age_secs"))?
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_AVATAR_MIN_AGE);
+    let dry_run: bool = get_opt_str_parse(kwargs.get("dry_run"))?.unwrap_or(false);
+
+    let mut keep = HashSet::new();
+    for (game_id, handle) in STATE.get_handles() {
+        match handle.run(|game| game.get_avatar_urls()).await {
+            Ok(avatar_urls) => keep.extend(avatar_urls),
+            Err(Error::UnknownGame) => {}
+            // Without this game's avatars, there's no telling which files are unused.
+            Err(err) => {
+                warn!("Could not get avatars for game {:?}: {:?}", game_id, err);
+                return Err(err.into());
+            }
+        }
+    }
+    keep.extend(
+        PROFILE_MANAGER
+            .lock()
+            .await
+            .get_avatar_urls()
+            .map(str::to_string),
+    );
+
+    let (removed, bytes) = AVATAR_MANAGER
+        .lock()
+        .await
+        .purge(&keep, min_age, dry_run)
+        .await?;
+    info!(
+        "Purged {} avatars ({} bytes; dry run: {})",
+        removed, bytes, dry_run
+    );
+
+    let mut result = WampKwArgs::new();
+    result.insert("removed".into(), WampPayloadValue::Number(removed.into()));
+    result.insert("bytes".into(), WampPayloadValue::Number(bytes.into()));
+    result.insert("dry_run".into(), WampPayloadValue::Bool(dry_run));
+    Ok((None, Some(result)))
+}
+
+/// Get server-wide numbers: games, players, profiles, the dataset and uptime
+pub async fn get_stats(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("admin::get_stats");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    check_token(&kwargs)?;
+
+    let (mut games, mut private_games, mut players, mut spectators) = (0, 0, 0, 0);
+    for (game_id, handle) in STATE.get_handles() {
+        let result = handle
+            .run(|game| {
+                (
+                    game.is_private,
+                    game.get_player_names().len(),
+                    game.get_spectator_count(),
+                )
+            })
+            .await;
+        match result {
+            Ok((is_private, player_count, spectator_count)) => {
+                games += 1;
+                if is_private {
+                    private_games += 1;
+                }
+                players += player_count;
+                spectators += spectator_count;
+            }
+            Err(err) => warn!("Could not get game {:?}: {:?}", game_id, err),
+        }
+    }
+
+    let mut result = serialize_dataset_stats(&JEOPARDY_DATA.read());
+    result.insert("games".into(), WampPayloadValue::Number(games.into()));
+    result.insert(
+        "private_games".into(),
+        WampPayloadValue::Number(private_games.into()),
+    );
+    result.insert(
+        "max_games".into(),
+        WampPayloadValue::Number(CONFIG.get().unwrap().limits.max_games.into()),
+    );
+    result.insert("players".into(), WampPayloadValue::Number(players.into()));
+    result.insert(
+        "spectators".into(),
+        WampPayloadValue::Number(spectators.into()),
+    );
+    result.insert(
+        "profiles".into(),
+        WampPayloadValue::Number(PROFILE_MANAGER.lock().await.get_profile_count().into()),
+    );
+    result.insert(
+        "started_at".into(),
+        WampPayloadValue::String(STARTED_AT.to_rfc3339()),
+    );
+    result.insert(
+        "uptime_secs".into(),
+        WampPayloadValue::Number((Utc::now() - *STARTED_AT).num_seconds().into()),
+    );
+    Ok((None, Some(result)))
+}
+
+fn serialize_dataset_stats(jeopardy_data: &data::JeopardyData) -> WampKwArgs {
+    let mut result = WampKwArgs::new();
+    result.insert(
+        "categories".into(),
+        WampPayloadValue::Number(jeopardy_data.categories.len().into()),
+    );
+    result.insert(
+        "final_jeopardy_questions".into(),
+        WampPayloadValue::Number(jeopardy_data.final_jeopardy_questions.len().into()),
+    );
+    result.insert(
+        "min_year".into(),
+        WampPayloadValue::Number(jeopardy_data.min_year.into()),
+    );
+    result.insert(
+        "max_year".into(),
+        WampPayloadValue::Number(jeopardy_data.max_year.into()),
+    );
+    result
+}
+
+#[cfg(test)]
+mod admin_tests {
+    use super::{tokens_match, AdminConfig};
+
+    #[test]
+    fn checks_and_hides_token() {
+        assert!(tokens_match(
+            "correct horse battery",
+            "correct horse battery"
+        ));
+        assert!(!tokens_match("correct horse", "correct horse battery"));
+        assert!(!tokens_match("", "correct horse battery"));
+
+        let config = AdminConfig {
+            token: Some("correct horse battery".into()),
+        };
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("horse"));
+        assert!(debug.contains("<redacted>"));
+    }
+}
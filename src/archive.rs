@@ -0,0 +1,487 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use wamp_async::{WampKwArgs, WampPayloadValue};
+
+use crate::{
+    errors::Error,
+    game::{
+        board::{Location, SquareState},
+        events::{GameEvent, LoggedEvent, SquareStatus},
+        AnswerType,
+    },
+    GameId, PlayerId,
+};
+
+/// Everything needed to review a finished game: who played, how it ended, and the full event
+/// log (which includes each board's seed and every clue that was played).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Transcript {
+    pub game_id: GameId,
+    pub moderator: String,
+    pub time_started: DateTime<Utc>,
+    pub time_ended: DateTime<Utc>,
+    pub players: Vec<TranscriptPlayer>, // players still in the game when it ended
+    pub events: Vec<LoggedEvent>,
+    // Everyone who played or watched had this, so it's what lets them review the game. Archives
+    // from before it was kept can't be reviewed.
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscriptPlayer {
+    pub id: PlayerId,
+    pub name: String,
+    pub avatar_url: String,
+    pub final_score: i64,
+}
+
+impl Transcript {
+    /// Whether a board or Final Jeopardy was ever put up.
+    pub fn got_going(&self) -> bool {
+        self.events.iter().any(|logged| logged.event.starts_play())
+    }
+
+    /// Serializes everything but the events.
+    pub fn serialize_summary(&self) -> WampKwArgs {
+        let mut result = wamp_dict! {
+            "game_id" => self.game_id.to_string(),
+            "moderator" => self.moderator.clone(),
+            "time_started" => self.time_started.to_rfc3339(),
+            "time_ended" => self.time_ended.to_rfc3339(),
+        };
+        result.insert(
+            "players".into(),
+            WampPayloadValue::Array(
+                self.players
+                    .iter()
+                    .map(|player| {
+                        WampPayloadValue::Object(wamp_dict! {
+                            "id" => player.id.to_string(),
+                            "name" => player.name.clone(),
+                            "avatar_url" => player.avatar_url.clone(),
+                            "final_score" => player.final_score.to_string(),
+                        })
+                    })
+                    .collect(),
+            ),
+        );
+        result.insert(
+            "step_count".into(),
+            WampPayloadValue::Number(self.events.len().into()),
+        );
+        result
+    }
+
+    /// Whether the invite code is the one the game had.
+    pub fn check_invite_code(&self, invite_code: &str) -> bool {
+        self.invite_code.as_deref() == Some(invite_code)
+    }
+
+    /// Replays the game up to and including the event at `step`.
+    pub fn review(&self, step: usize) -> Option<ReviewStep<'_>> {
+        let event = self.events.get(step)?;
+
+        let mut names: HashMap<&PlayerId, &str> = self
+            .players
+            .iter()
+            .map(|player| (&player.id, player.name.as_str()))
+            .collect();
+        let mut scores: HashMap<&PlayerId, i64> = HashMap::new();
+        let mut boards: HashMap<usize, &LoggedEvent> = HashMap::new();
+        let mut board = None;
+        let mut squares: HashMap<Location, SquareState> = HashMap::new();
+        let mut clue = None;
+        let mut clue_is_daily_double = false;
+
+        for logged in &self.events[..=step] {
+            match &logged.event {
+                GameEvent::PlayerJoined { player, name } => {
+                    names.insert(player, name);
+                    scores.insert(player, 0);
+                }
+                GameEvent::PlayerLeft { player, .. } => {
+                    scores.remove(player);
+                }
+                GameEvent::BoardLoaded { board_id, .. } => {
+                    boards.insert(*board_id, logged);
+                    board = Some(logged);
+                    squares.clear();
+                    clue = None;
+                }
+                GameEvent::FinalJeopardyStarted { .. } => {
+                    board = Some(logged);
+                    squares.clear();
+                    clue = None;
+                }
+                GameEvent::SquareSelected {
+                    location,
+                    is_daily_double,
+                    ..
+                } => {
+                    clue = Some(logged);
+                    clue_is_daily_double = *is_daily_double;
+                    squares.insert(
+                        *location,
+                        if *is_daily_double {
+                            SquareState::DailyDoubleRevealed
+                        } else {
+                            SquareState::Flipped
+                        },
+                    );
+                }
+                GameEvent::DailyDoubleClueRevealed { location } => {
+                    squares.insert(*location, SquareState::Flipped);
+                }
+                GameEvent::ClueSkipped { location } => {
+                    squares.insert(*location, SquareState::Finished);
+                }
+                GameEvent::SquareStateChanged { location, state } => {
+                    squares.insert(*location, state.clone());
+                }
+                GameEvent::AnswerJudged {
+                    player,
+                    judgment,
+                    score,
+                    ..
+                } => {
+                    scores.insert(player, *score);
+                    // A right answer ends the clue; so does any answer to a Daily Double
+                    if *judgment == AnswerType::Correct || clue_is_daily_double {
+                        if let Some(GameEvent::SquareSelected { location, .. }) =
+                            clue.map(|clue| &clue.event)
+                        {
+                            squares.insert(*location, SquareState::Finished);
+                        }
+                    }
+                }
+                GameEvent::FinalJeopardyJudged { player, score, .. }
+                | GameEvent::ScoreChanged {
+                    player,
+                    new_score: score,
+                    ..
+                } => {
+                    scores.insert(player, *score);
+                }
+                GameEvent::Undo {
+                    scores: new_scores,
+                    board_id,
+                    squares: new_squares,
+                }
+                | GameEvent::Redo {
+                    scores: new_scores,
+                    board_id,
+                    squares: new_squares,
+                } => {
+                    scores = new_scores
+                        .iter()
+                        .map(|(player, score)| (player, *score))
+                        .collect();
+                    match board_id {
+                        Some(board_id) => board = boards.get(board_id).copied(),
+                        // Either there's no board, or it's Final Jeopardy (which can't go back to
+                        // not having started)
+                        None => {
+                            board = board.filter(|board| {
+                                matches!(board.event, GameEvent::FinalJeopardyStarted { .. })
+                            })
+                        }
+                    }
+                    squares = new_squares
+                        .iter()
+                        .map(|status| (status.location, status.state.clone()))
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+
+        let mut squares: Vec<SquareStatus> = squares
+            .into_iter()
+            .map(|(location, state)| SquareStatus { location, state })
+            .collect();
+        squares.sort_by_key(|status| status.location);
+
+        Some(ReviewStep {
+            event,
+            scores: scores
+                .into_iter()
+                .map(|(player, score)| {
+                    let name = names.get(player).copied().unwrap_or_default();
+                    (player.clone(), (name.to_string(), score))
+                })
+                .collect(),
+            board,
+            squares,
+            clue,
+        })
+    }
+}
+
+/// What a game looked like right after one of its events.
+#[derive(Debug)]
+pub struct ReviewStep<'a> {
+    pub event: &'a LoggedEvent,
+    pub scores: HashMap<PlayerId, (String, i64)>, // name and score of everyone playing
+    pub board: Option<&'a LoggedEvent>,           // the most recently loaded board
+    pub squares: Vec<SquareStatus>,               // the squares played on it so far
+    pub clue: Option<&'a LoggedEvent>,            // the clue in play on that board, if any
+}
+impl<'a> ReviewStep<'a> {
+    pub fn serialize(&self) -> WampKwArgs {
+        let mut result = WampKwArgs::new();
+        result.insert(
+            "event".into(),
+            WampPayloadValue::Object(self.event.serialize()),
+        );
+        result.insert(
+            "scores".into(),
+            WampPayloadValue::Object(
+                self.scores
+                    .iter()
+                    .map(|(player_id, (name, score))| {
+                        (
+                            player_id.to_string(),
+                            WampPayloadValue::Object(wamp_dict! {
+                                "name" => name.clone(),
+                                "score" => score.to_string(),
+                            }),
+                        )
+                    })
+                    .collect(),
+            ),
+        );
+        if let Some(board) = self.board {
+            result.insert("board".into(), WampPayloadValue::Object(board.serialize()));
+        }
+        result.insert(
+            "squares".into(),
+            serde_json::to_value(&self.squares).expect("squares always serialize"),
+        );
+        if let Some(clue) = self.clue {
+            result.insert("clue".into(), WampPayloadValue::Object(clue.serialize()));
+        }
+        result
+    }
+}
+
+/// Stores transcripts of finished games as JSON files.
+pub(crate) struct ArchiveManager {
+    directory: PathBuf,
+}
+impl ArchiveManager {
+    pub(crate) fn new(directory: PathBuf) -> Self {
+        ArchiveManager { directory }
+    }
+
+    fn get_path(&self, game_id: &GameId) -> PathBuf {
+        self.directory.join(format!("{}.json", game_id))
+    }
+
+    pub(crate) async fn save(&self, transcript: &Transcript) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+        let bytes = serde_json::to_vec(transcript)?;
+        tokio::fs::write(self.get_path(&transcript.game_id), bytes).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn load(&self, game_id: &GameId) -> Result<Transcript, Error> {
+        let bytes = match tokio::fs::read(self.get_path(game_id)).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::UnknownGame)
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::Transcript;
+    use crate::{
+        game::{
+            board::{Clue, Location, SquareState},
+            events::{GameEvent, LoggedEvent, SquareStatus},
+            AnswerType,
+        },
+        GameId, PlayerId,
+    };
+
+    fn select(location: Location, is_daily_double: bool) -> GameEvent {
+        GameEvent::SquareSelected {
+            location,
+            category: "Potent Potables".into(),
+            value: 200,
+            is_daily_double,
+            clue: Clue {
+                text: Some("A clue".into()),
+                link: None,
+            },
+            answer: "An answer".into(),
+        }
+    }
+
+    fn judge(player: &PlayerId, judgment: AnswerType, score: i64) -> GameEvent {
+        GameEvent::AnswerJudged {
+            player: player.clone(),
+            judgment,
+            value: 200,
+            score,
+        }
+    }
+
+    fn status(category: usize, row: usize, state: SquareState) -> SquareStatus {
+        SquareStatus {
+            location: Location::new(category, row).unwrap(),
+            state,
+        }
+    }
+
+    fn transcript(events: Vec<GameEvent>) -> Transcript {
+        Transcript {
+            game_id: GameId(Uuid::new_v4()),
+            moderator: "Moderator".into(),
+            time_started: Utc::now(),
+            time_ended: Utc::now(),
+            players: Vec::new(),
+            events: events
+                .into_iter()
+                .enumerate()
+                .map(|(index, event)| LoggedEvent {
+                    index,
+                    time: Utc::now(),
+                    event,
+                })
+                .collect(),
+            invite_code: Some("code".into()),
+        }
+    }
+
+    #[test]
+    fn replays_squares() {
+        let alice = PlayerId(Uuid::new_v4());
+        let first = Location::new(0, 0).unwrap();
+        let daily_double = Location::new(1, 2).unwrap();
+        let events = vec![
+            GameEvent::PlayerJoined {
+                player: alice.clone(),
+                name: "alice".into(),
+            },
+            GameEvent::BoardLoaded {
+                board_id: 1,
+                seed: "seed".into(),
+                value_multiplier: 200,
+                categories: vec!["Potent Potables".into()],
+            },
+            select(first, false),
+            judge(&alice, AnswerType::Incorrect, -200),
+            judge(&alice, AnswerType::Correct, 0),
+            select(daily_double, true),
+            GameEvent::DailyDoubleClueRevealed {
+                location: daily_double,
+            },
+            GameEvent::Undo {
+                scores: vec![(alice.clone(), 0)].into_iter().collect(),
+                board_id: Some(1),
+                squares: vec![
+                    status(0, 0, SquareState::Finished),
+                    status(1, 2, SquareState::DailyDoubleRevealed),
+                ],
+            },
+            GameEvent::SquareStateChanged {
+                location: first,
+                state: SquareState::Normal,
+            },
+            GameEvent::ClueSkipped {
+                location: daily_double,
+            },
+        ];
+        let transcript = transcript(events);
+
+        let squares_at = |step| transcript.review(step).unwrap().squares;
+        assert!(squares_at(1).is_empty());
+        assert_eq!(squares_at(2), [status(0, 0, SquareState::Flipped)]);
+        // Wrong answers leave the clue open for someone else
+        assert_eq!(squares_at(3), [status(0, 0, SquareState::Flipped)]);
+        assert_eq!(squares_at(4), [status(0, 0, SquareState::Finished)]);
+        assert_eq!(
+            squares_at(5),
+            [
+                status(0, 0, SquareState::Finished),
+                status(1, 2, SquareState::DailyDoubleRevealed)
+            ]
+        );
+        assert_eq!(
+            squares_at(6),
+            [
+                status(0, 0, SquareState::Finished),
+                status(1, 2, SquareState::Flipped)
+            ]
+        );
+        assert_eq!(
+            squares_at(7),
+            [
+                status(0, 0, SquareState::Finished),
+                status(1, 2, SquareState::DailyDoubleRevealed)
+            ]
+        );
+        assert_eq!(
+            squares_at(9),
+            [
+                status(0, 0, SquareState::Normal),
+                status(1, 2, SquareState::Finished)
+            ]
+        );
+
+        let review = transcript.review(7).unwrap();
+        assert_eq!(review.scores[&alice], ("alice".to_string(), 0));
+        assert!(review.board.is_some());
+        assert!(transcript.review(10).is_none());
+
+        assert!(transcript.check_invite_code("code"));
+        assert!(!transcript.check_invite_code("other"));
+    }
+
+    #[test]
+    fn reads_undo_without_squares() {
+        let event: GameEvent = serde_json::from_value(serde_json::json!({
+            "type": "Undo",
+            "scores": {},
+        }))
+        .unwrap();
+        assert!(matches!(
+            event,
+            GameEvent::Undo { board_id: None, ref squares, .. } if squares.is_empty()
+        ));
+    }
+    #[test]
+    fn only_games_with_a_board_got_going() {
+        let alice = PlayerId(Uuid::new_v4());
+        let mut events = vec![
+            GameEvent::PlayerJoined {
+                player: alice.clone(),
+                name: "alice".into(),
+            },
+            GameEvent::PlayerLeft {
+                player: alice,
+                name: "alice".into(),
+            },
+        ];
+        assert!(!transcript(events.clone()).got_going());
+
+        events.push(GameEvent::BoardLoaded {
+            board_id: 1,
+            seed: "seed".into(),
+            value_multiplier: 200,
+            categories: vec!["Potent Potables".into()],
+        });
+        assert!(transcript(events).got_going());
+    }
+}
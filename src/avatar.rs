@@ -1,4 +1,4 @@
-use std::{io::Write, path::PathBuf};
+use std::{collections::HashSet, io::Write, path::PathBuf, time::Duration};
 
 use sha2::{Digest, Sha256};
 use tokio::io::AsyncWriteExt;
@@ -60,4 +60,46 @@ impl AvatarManager {
 
         Ok(format!("{}/{}", self.prefix, filename))
     }
+
+    /// Deletes every saved avatar that isn't in `keep` (a set of avatar URLs, as returned by
+    /// `save_avatar`) and is at least `min_age` old. Young avatars are left alone because someone
+    /// may have just uploaded one and not joined a game with it yet. Returns how many files were
+    /// (or, with `dry_run`, would be) deleted, and how many bytes they took up.
+    pub(crate) async fn purge(
+        &self,
+        keep: &HashSet<String>,
+        min_age: Duration,
+        dry_run: bool,
+    ) -> Result<(usize, u64), Error> {
+        let mut removed = 0;
+        let mut bytes = 0;
+
+        let mut entries = tokio::fs::read_dir(&self.directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let url = format!("{}/{}", self.prefix, entry.file_name().to_string_lossy());
+            if keep.contains(&url) {
+                continue;
+            }
+            let age = metadata
+                .modified()?
+                .elapsed()
+                .unwrap_or(Duration::from_secs(0));
+            if age < min_age {
+                continue;
+            }
+
+            if !dry_run {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+            removed += 1;
+            bytes += metadata.len();
+        }
+
+        Ok((removed, bytes))
+    }
 }
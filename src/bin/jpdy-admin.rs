@@ -0,0 +1,232 @@
+//! Calls the game server's admin RPCs (`jpdy.admin.*`) through the WAMP router and prints what
+//! comes back as JSON.
+
+use std::{env, process};
+
+use wamp_async::{Client, ClientConfig, SerializerType, WampError, WampKwArgs, WampPayloadValue};
+
+const USAGE: &str = "Usage: jpdy-admin [options] <command> [arguments]
+
+Commands:
+    games                           List every running game, private ones included
+    stats                           Show server-wide numbers
+    end GAME_ID                     End a game and archive it
+    kick GAME_ID PLAYER_ID          Remove a player from a game
+        [--ban] [--reason TEXT]
+    reload-data                     Load the clue dataset again from the server's database path
+    purge-avatars                   Delete avatars nobody is using
+        [--min-age-secs N] [--dry-run]
+
+Options:
+    --router-url URL    WebSocket URL of the WAMP router (default: $JPDY_ROUTER_URL, or
+                        ws://127.0.0.1:8080/ws)
+    --realm NAME        WAMP realm to join (default: $JPDY_REALM, or jpdy)
+    --token TOKEN       The server's admin token (default: $JPDY_ADMIN_TOKEN)
+    --help              Show this message";
+
+/// What to call, and where.
+#[derive(Debug)]
+struct Request {
+    router_url: String,
+    realm: String,
+    procedure: &'static str,
+    kwargs: WampKwArgs,
+}
+
+#[derive(Debug, Default)]
+struct Flags {
+    router_url: Option<String>,
+    realm: Option<String>,
+    token: Option<String>,
+    ban: bool,
+    reason: Option<String>,
+    min_age_secs: Option<String>,
+    dry_run: bool,
+}
+
+/// Works out the request from the command line, looking up anything left out with `var`. Returns
+/// `None` if the user asked for help.
+fn parse_args<I, F>(args: I, var: F) -> Result<Option<Request>, String>
+where
+    I: IntoIterator<Item = String>,
+    F: Fn(&str) -> Option<String>,
+{
+    let mut flags = Flags::default();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--help" => return Ok(None),
+            "--router-url" => flags.router_url = Some(value()?),
+            "--realm" => flags.realm = Some(value()?),
+            "--token" => flags.token = Some(value()?),
+            "--ban" => flags.ban = true,
+            "--reason" => flags.reason = Some(value()?),
+            "--min-age-secs" => {
+                let min_age_secs = value()?;
+                if min_age_secs.parse::<u64>().is_err() {
+                    return Err(format!(
+                        "Invalid value for --min-age-secs: {:?}",
+                        min_age_secs
+                    ));
+                }
+                flags.min_age_secs = Some(min_age_secs);
+            }
+            "--dry-run" => flags.dry_run = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let token = flags
+        .token
+        .take()
+        .or_else(|| var("JPDY_ADMIN_TOKEN"))
+        .ok_or("No admin token; pass --token or set JPDY_ADMIN_TOKEN")?;
+    let mut kwargs = WampKwArgs::new();
+    kwargs.insert("admin_token".into(), token.into());
+
+    let positional = positional.iter().map(String::as_str).collect::<Vec<_>>();
+    let procedure = match positional.as_slice() {
+        ["games"] => "jpdy.admin.list_games",
+        ["stats"] => "jpdy.admin.stats",
+        ["end", game_id] => {
+            kwargs.insert("game_id".into(), (*game_id).into());
+            "jpdy.admin.end_game"
+        }
+        ["kick", game_id, player_id] => {
+            kwargs.insert("game_id".into(), (*game_id).into());
+            kwargs.insert("target".into(), (*player_id).into());
+            kwargs.insert("ban".into(), flags.ban.to_string().into());
+            if let Some(reason) = flags.reason.take() {
+                kwargs.insert("reason".into(), reason.into());
+            }
+            "jpdy.admin.kick"
+        }
+        ["reload-data"] => "jpdy.admin.reload_data",
+        ["purge-avatars"] => {
+            if let Some(min_age_secs) = flags.min_age_secs.take() {
+                kwargs.insert("min_age_secs".into(), min_age_secs.into());
+            }
+            kwargs.insert("dry_run".into(), flags.dry_run.to_string().into());
+            "jpdy.admin.purge_avatars"
+        }
+        [] => return Err("No command given".into()),
+        [command, ..] => return Err(format!("Unknown command or wrong arguments: {}", command)),
+    };
+
+    // Anything the command didn't use was given to the wrong one.
+    if (flags.ban && procedure != "jpdy.admin.kick") || flags.reason.is_some() {
+        return Err("--ban and --reason only go with kick".into());
+    }
+    if (flags.dry_run && procedure != "jpdy.admin.purge_avatars") || flags.min_age_secs.is_some() {
+        return Err("--min-age-secs and --dry-run only go with purge-avatars".into());
+    }
+
+    Ok(Some(Request {
+        router_url: flags
+            .router_url
+            .or_else(|| var("JPDY_ROUTER_URL"))
+            .unwrap_or_else(|| "ws://127.0.0.1:8080/ws".into()),
+        realm: flags
+            .realm
+            .or_else(|| var("JPDY_REALM"))
+            .unwrap_or_else(|| "jpdy".into()),
+        procedure,
+        kwargs,
+    }))
+}
+
+async fn call(request: Request) -> Result<Option<WampKwArgs>, WampError> {
+    let (mut client, (event_loop, _)) = Client::connect(
+        &request.router_url,
+        Some(ClientConfig::default().set_serializers(vec![SerializerType::Json])),
+    )
+    .await?;
+    tokio::spawn(event_loop);
+
+    client.join_realm(request.realm).await?;
+    let result = client
+        .call(request.procedure, None, Some(request.kwargs))
+        .await;
+
+    let _ = client.leave_realm().await;
+    client.disconnect().await;
+    Ok(result?.1)
+}
+
+#[tokio::main]
+async fn main() {
+    let request = match parse_args(env::args().skip(1), |name| env::var(name).ok()) {
+        Ok(Some(request)) => request,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match call(request).await {
+        Ok(Some(kwargs)) => println!(
+            "{}",
+            serde_json::to_string_pretty(&WampPayloadValue::Object(kwargs)).unwrap()
+        ),
+        Ok(None) => println!("Done."),
+        Err(WampError::UnknownError(code)) => {
+            eprintln!("The server said no: {}", code);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("Call failed: {:?}", err);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod admin_cli_tests {
+    use super::parse_args;
+
+    fn parse(args: &[&str]) -> Result<Option<super::Request>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()), |name| match name {
+            "JPDY_ADMIN_TOKEN" => Some("from the environment".into()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn builds_requests() {
+        let request = parse(&["kick", "game", "player", "--ban", "--token", "secret"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.procedure, "jpdy.admin.kick");
+        assert_eq!(request.router_url, "ws://127.0.0.1:8080/ws");
+        assert_eq!(request.kwargs["admin_token"], "secret");
+        assert_eq!(request.kwargs["target"], "player");
+        assert_eq!(request.kwargs["ban"], "true");
+
+        let request = parse(&["purge-avatars", "--min-age-secs", "60"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.procedure, "jpdy.admin.purge_avatars");
+        assert_eq!(request.kwargs["admin_token"], "from the environment");
+        assert_eq!(request.kwargs["min_age_secs"], "60");
+        assert_eq!(request.kwargs["dry_run"], "false");
+
+        assert!(parse(&["--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["end"]).is_err());
+        assert!(parse(&["restart"]).is_err());
+        assert!(parse(&["stats", "--ban"]).is_err());
+        assert!(parse(&["purge-avatars", "--min-age-secs", "soon"]).is_err());
+    }
+}
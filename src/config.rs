@@ -0,0 +1,465 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    admin::{self, AdminConfig},
+    gc::{self, GcConfig},
+    metrics::MetricsConfig,
+};
+
+const CONFIG_ENV_NAME: &str = "JPDY_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "jeopardy.toml";
+
+pub const USAGE: &str = "Usage: jeopardy [options]
+
+Options:
+    --config PATH       Read settings from this TOML file (default: jeopardy.toml, if it exists)
+    --router-url URL    WebSocket URL of the WAMP router (default: ws://127.0.0.1:8080/ws)
+    --realm NAME        WAMP realm to join
+    --database PATH     Path to the gzipped clue database
+    --max-games N       How many games can run at once
+    --help              Show this message";
+
+/// Everything about the server that can be configured. Settings come from the defaults, then the
+/// TOML file, then environment variables, then command line flags, each overriding the last.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub router: RouterConfig,
+    pub paths: PathConfig,
+    pub limits: LimitConfig,
+    pub gc: GcConfig,
+    pub metrics: MetricsConfig,
+    pub admin: AdminConfig,
+    pub game_defaults: GameDefaults,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouterConfig {
+    pub url: String,
+    pub realm: String,
+    // Reconnecting backs off up to this long between attempts
+    #[serde(
+        rename = "max_reconnect_delay_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub max_reconnect_delay: Duration,
+}
+impl Default for RouterConfig {
+    fn default() -> Self {
+        RouterConfig {
+            url: "ws://127.0.0.1:8080/ws".into(),
+            realm: "jpdy".into(),
+            max_reconnect_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathConfig {
+    pub database: PathBuf,
+    pub archive: PathBuf,
+    pub profiles: PathBuf,
+    pub avatars: PathBuf,
+    pub avatar_url_prefix: String, // where the web server serves `avatars` from
+}
+impl Default for PathConfig {
+    fn default() -> Self {
+        PathConfig {
+            database: "jeo_data_utf8.csv.gz".into(),
+            archive: "archive".into(),
+            profiles: "profiles.json".into(),
+            avatars: ["static", "avatars"].iter().collect(),
+            avatar_url_prefix: "avatars".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitConfig {
+    #[serde(
+        rename = "operation_timeout_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub operation_timeout: Duration,
+    pub max_games: usize,
+    pub max_avatar_size: usize,
+    pub new_game_burst: u32,
+    #[serde(rename = "new_game_period_secs", deserialize_with = "deserialize_secs")]
+    pub new_game_period: Duration,
+    pub player_call_burst: u32,
+    #[serde(
+        rename = "player_call_period_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub player_call_period: Duration,
+    pub game_call_burst: u32,
+    #[serde(
+        rename = "game_call_period_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub game_call_period: Duration,
+    pub join_burst: u32,
+    #[serde(rename = "join_period_secs", deserialize_with = "deserialize_secs")]
+    pub join_period: Duration,
+    pub profile_burst: u32,
+    #[serde(rename = "profile_period_secs", deserialize_with = "deserialize_secs")]
+    pub profile_period: Duration,
+    pub invite_burst: u32,
+    #[serde(rename = "invite_period_secs", deserialize_with = "deserialize_secs")]
+    pub invite_period: Duration,
+    pub max_buffered_messages: usize, // held while the router is unreachable
+}
+impl Default for LimitConfig {
+    fn default() -> Self {
+        LimitConfig {
+            operation_timeout: Duration::from_secs(5),
+            max_games: 500,
+            max_avatar_size: 32 * 1024,
+            new_game_burst: 10,
+            new_game_period: Duration::from_secs(60),
+            player_call_burst: 10,
+            player_call_period: Duration::from_secs(2),
+            game_call_burst: 50,
+            game_call_period: Duration::from_secs(2),
+            join_burst: 20,
+            join_period: Duration::from_secs(60),
+            profile_burst: 5,
+            profile_period: Duration::from_secs(60),
+            invite_burst: 20,
+            invite_period: Duration::from_secs(60),
+            max_buffered_messages: 10_000,
+        }
+    }
+}
+
+/// The rules new games start with, unless the moderator asks for something else.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameDefaults {
+    pub private: bool,
+    pub max_players: Option<usize>,
+    pub approve_joins: bool,
+}
+
+/// Reads a whole number of seconds as a `Duration`.
+pub fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    Ok(Duration::from_secs(u64::deserialize(deserializer)?))
+}
+
+/// The command line flags. Anything left out doesn't override the config.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub router_url: Option<String>,
+    pub realm: Option<String>,
+    pub database: Option<PathBuf>,
+    pub max_games: Option<usize>,
+    pub help: bool,
+}
+impl Args {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut result = Args::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" {
+                result.help = true;
+                continue;
+            }
+
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--config" => result.config = Some(value()?.into()),
+                "--router-url" => result.router_url = Some(value()?),
+                "--realm" => result.realm = Some(value()?),
+                "--database" => result.database = Some(value()?.into()),
+                "--max-games" => result.max_games = Some(parse_value(&arg, &value()?)?),
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {:?}", name, value))
+}
+
+impl Config {
+    /// Loads the config for this run of the server and checks that it makes sense.
+    pub fn load(args: &Args) -> Result<Self, String> {
+        let path = args
+            .config
+            .clone()
+            .or_else(|| env::var_os(CONFIG_ENV_NAME).map(PathBuf::from));
+        let mut config = match path {
+            Some(path) => Config::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Config::default(),
+        };
+
+        config.apply_env(|name| env::var(name).ok())?;
+        config.apply_args(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+        Config::from_toml(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    fn from_toml(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents).map_err(|err| err.to_string())
+    }
+
+    /// Applies overrides from environment variables, looked up with `var`.
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) -> Result<(), String> {
+        // The router used to always be on localhost, so only its port could be set.
+        if let Some(port) = var("JPDY_ROUTER_PORT") {
+            let port: u16 = parse_value("JPDY_ROUTER_PORT", &port)?;
+            self.router.url = format!("ws://127.0.0.1:{}/ws", port);
+        }
+        if let Some(url) = var("JPDY_ROUTER_URL") {
+            self.router.url = url;
+        }
+        if let Some(realm) = var("JPDY_REALM") {
+            self.router.realm = realm;
+        }
+        if let Some(database) = var("JPDY_DATABASE") {
+            self.paths.database = database.into();
+        }
+        if let Some(max_games) = var("JPDY_MAX_GAMES") {
+            self.limits.max_games = parse_value("JPDY_MAX_GAMES", &max_games)?;
+        }
+
+        if let Some(token) = var("JPDY_ADMIN_TOKEN") {
+            self.admin.token = Some(token);
+        }
+        if let Some(address) = var("JPDY_METRICS_ADDRESS") {
+            self.metrics.address = parse_value("JPDY_METRICS_ADDRESS", &address)?;
+        }
+
+        let secs = |name: &str| -> Result<Option<Duration>, String> {
+            var(name)
+                .map(|value| parse_value(name, &value).map(Duration::from_secs))
+                .transpose()
+        };
+        if let Some(interval) = secs("JPDY_GC_INTERVAL_SECS")? {
+            self.gc.interval = interval;
+        }
+        if let Some(idle_timeout) = secs("JPDY_GC_IDLE_TIMEOUT_SECS")? {
+            self.gc.idle_timeout = idle_timeout;
+        }
+        if let Some(warning_period) = secs("JPDY_GC_WARNING_SECS")? {
+            self.gc.warning_period = warning_period;
+        }
+        if let Some(spectator_timeout) = secs("JPDY_GC_SPECTATOR_TIMEOUT_SECS")? {
+            self.gc.spectator_timeout = spectator_timeout;
+        }
+
+        Ok(())
+    }
+
+    fn apply_args(&mut self, args: &Args) {
+        if let Some(url) = &args.router_url {
+            self.router.url = url.clone();
+        }
+        if let Some(realm) = &args.realm {
+            self.router.realm = realm.clone();
+        }
+        if let Some(database) = &args.database {
+            self.paths.database = database.clone();
+        }
+        if let Some(max_games) = args.max_games {
+            self.limits.max_games = max_games;
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self.router.url.starts_with("ws://") && !self.router.url.starts_with("wss://") {
+            return Err(format!(
+                "router.url must be a ws:// or wss:// URL, not {:?}",
+                self.router.url
+            ));
+        }
+        if self.router.realm.is_empty() {
+            return Err("router.realm can't be empty".into());
+        }
+        if self.router.max_reconnect_delay == Duration::from_secs(0) {
+            return Err("router.max_reconnect_delay_secs must be at least 1".into());
+        }
+
+        let limits = &self.limits;
+        if limits.operation_timeout == Duration::from_secs(0) {
+            return Err("limits.operation_timeout_secs must be at least 1".into());
+        }
+        if limits.max_games == 0 {
+            return Err("limits.max_games must be at least 1".into());
+        }
+        if limits.max_buffered_messages == 0 {
+            return Err("limits.max_buffered_messages must be at least 1".into());
+        }
+        for (name, burst, period) in &[
+            ("new_game", limits.new_game_burst, limits.new_game_period),
+            (
+                "player_call",
+                limits.player_call_burst,
+                limits.player_call_period,
+            ),
+            ("game_call", limits.game_call_burst, limits.game_call_period),
+            ("join", limits.join_burst, limits.join_period),
+            ("profile", limits.profile_burst, limits.profile_period),
+            ("invite", limits.invite_burst, limits.invite_period),
+        ] {
+            if *burst == 0 || *period == Duration::from_secs(0) {
+                return Err(format!(
+                    "limits.{0}_burst and limits.{0}_period_secs must be at least 1",
+                    name
+                ));
+            }
+        }
+
+        if self.gc.interval == Duration::from_secs(0) {
+            return Err("gc.interval_secs must be at least 1".into());
+        }
+        for (name, duration) in &[
+            ("interval", self.gc.interval),
+            ("idle_timeout", self.gc.idle_timeout),
+            ("warning", self.gc.warning_period),
+            ("spectator_timeout", self.gc.spectator_timeout),
+        ] {
+            if *duration > gc::MAX_DURATION {
+                return Err(format!("gc.{}_secs can be at most a year", name));
+            }
+        }
+        if self.gc.warning_period >= self.gc.idle_timeout {
+            return Err("gc.warning_secs must be shorter than gc.idle_timeout_secs".into());
+        }
+        // Clients check in every 30 seconds
+        if self.gc.spectator_timeout < Duration::from_secs(60) {
+            return Err("gc.spectator_timeout_secs must be at least 60".into());
+        }
+
+        if let Some(token) = &self.admin.token {
+            if token.chars().count() < admin::MIN_TOKEN_LENGTH {
+                return Err(format!(
+                    "admin.token must be at least {} characters",
+                    admin::MIN_TOKEN_LENGTH
+                ));
+            }
+        }
+
+        if self.game_defaults.max_players == Some(0) {
+            return Err(
+                "game_defaults.max_players must be at least 1 (leave it out for no cap)".into(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use std::time::Duration;
+
+    use super::{Args, Config};
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn reads_toml() {
+        let config = Config::from_toml(
+            r#"
+            [router]
+            url = "wss://example.com/ws"
+
+            [gc]
+            idle_timeout_secs = 3600
+
+            [game_defaults]
+            approve_joins = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.router.url, "wss://example.com/ws");
+        assert_eq!(config.router.realm, "jpdy");
+        assert_eq!(config.gc.idle_timeout, Duration::from_secs(3600));
+        assert!(config.game_defaults.approve_joins);
+        assert!(Config::from_toml("[router]\nport = 8080").is_err());
+    }
+
+    #[test]
+    fn later_sources_override_earlier_ones() {
+        let mut config = Config::default();
+        config
+            .apply_env(|name| match name {
+                "JPDY_ROUTER_PORT" => Some("9000".into()),
+                "JPDY_GC_WARNING_SECS" => Some("60".into()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(config.router.url, "ws://127.0.0.1:9000/ws");
+        assert_eq!(config.gc.warning_period, Duration::from_secs(60));
+
+        config
+            .apply_args(&args(&["--router-url", "ws://router:80/ws", "--max-games", "3"]).unwrap());
+        assert_eq!(config.router.url, "ws://router:80/ws");
+        assert_eq!(config.limits.max_games, 3);
+        assert!(config.validate().is_ok());
+
+        assert!(config
+            .apply_env(|name| match name {
+                "JPDY_ROUTER_PORT" => Some("not a port".into()),
+                _ => None,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        assert!(args(&["--realm"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+
+        let mut config = Config::default();
+        config.router.url = "http://127.0.0.1/ws".into();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.gc.warning_period = config.gc.idle_timeout;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.gc.idle_timeout = Duration::from_secs(u64::MAX);
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.admin.token = Some("hunter2".into());
+        assert!(config.validate().is_err());
+    }
+}
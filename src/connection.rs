@@ -0,0 +1,314 @@
+use std::{collections::VecDeque, future::Future, time::Duration};
+
+use log::*;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use wamp_async::{Client, ClientConfig, SerializerType, WampError};
+
+use crate::{config::RouterConfig, register_rpcs, Message, CONFIG};
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How long to wait between attempts to reach the router: doubling after every failure, up to a
+/// limit, and back to the start once connected.
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+    max: Duration,
+}
+impl Backoff {
+    fn new(max: Duration) -> Self {
+        Backoff {
+            next: MIN_RECONNECT_DELAY.min(max),
+            max,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.next = MIN_RECONNECT_DELAY.min(self.max);
+    }
+}
+
+/// Messages waiting to be published. If the router is gone for long enough that too many pile
+/// up, the oldest are dropped; clients notice the gap from the versions and fetch the state again.
+struct Outbox {
+    messages: VecDeque<Message>,
+    capacity: usize,
+    dropped: usize,
+}
+impl Outbox {
+    fn new(capacity: usize) -> Self {
+        Outbox {
+            messages: VecDeque::new(),
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, message: Message) {
+        if self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+            self.dropped += 1;
+        }
+        self.messages.push_back(message);
+    }
+
+    /// Publishes everything waiting, in order. Whatever couldn't be sent stays for next time.
+    async fn flush(&mut self, client: &Client<'static>) -> Result<(), WampError> {
+        if self.dropped > 0 {
+            warn!(
+                "Dropped {} messages while the router was unreachable",
+                self.dropped
+            );
+            self.dropped = 0;
+        }
+
+        while let Some(message) = self.messages.front() {
+            client
+                .publish(
+                    message.topic.clone(),
+                    message.args.clone(),
+                    message.kwargs.clone(),
+                    false,
+                )
+                .await?;
+            self.messages.pop_front();
+        }
+        Ok(())
+    }
+}
+
+enum Disconnect {
+    Lost,
+    Shutdown,
+}
+
+async fn connect(config: &RouterConfig) -> Result<(Client<'static>, JoinHandle<()>), WampError> {
+    info!("Connecting to WAMP router at {}", config.url);
+    let (mut client, (event_loop, rpc_queue)) = Client::connect(
+        &config.url,
+        Some(ClientConfig::default().set_serializers(vec![SerializerType::Json])),
+    )
+    .await?;
+    info!("Connected!");
+
+    // Spawn the WAMP event loop (which enables processing messages out-of-order). It finishes
+    // when the connection drops.
+    let event_loop = tokio::spawn(async move {
+        if let Err(err) = event_loop.await {
+            warn!("WAMP event loop stopped: {:?}", err);
+        }
+    });
+
+    // Spawn each new RPC call on its own task.
+    let mut rpc_queue = rpc_queue.expect("Missing RPC event queue!");
+    tokio::spawn(async move {
+        while let Some(rpc_event) = rpc_queue.recv().await {
+            tokio::spawn(rpc_event);
+        }
+    });
+    info!("Event loop and RPC queue ready!");
+
+    let result = async {
+        client.join_realm(config.realm.clone()).await?;
+        info!("Joined realm {}!", config.realm);
+        register_rpcs(&client).await?;
+        info!("RPCs registered!");
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => Ok((client, event_loop)),
+        Err(err) => {
+            client.disconnect().await;
+            Err(err)
+        }
+    }
+}
+
+/// Publishes messages until the connection drops or it's time to shut down.
+async fn publish(
+    client: &Client<'static>,
+    event_loop: &mut JoinHandle<()>,
+    receiver: &mut mpsc::UnboundedReceiver<Message>,
+    outbox: &mut Outbox,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Disconnect {
+    loop {
+        if let Err(err) = outbox.flush(client).await {
+            warn!("Failed to publish: {:?}", err);
+            return Disconnect::Lost;
+        }
+
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(message) => outbox.push(message),
+                None => return Disconnect::Shutdown,
+            },
+            _ = &mut *event_loop => return Disconnect::Lost,
+            _ = shutdown.changed() => return Disconnect::Shutdown,
+        }
+    }
+}
+
+/// Waits for `future` while there's no connection, moving anything sent on `receiver` into the
+/// outbox in the meantime so that its limit applies. Returns `None` if it's time to shut down
+/// first.
+async fn buffer_until<F: Future>(
+    future: F,
+    receiver: &mut mpsc::UnboundedReceiver<Message>,
+    outbox: &mut Outbox,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Option<F::Output> {
+    tokio::pin!(future);
+    loop {
+        tokio::select! {
+            output = &mut future => return Some(output),
+            message = receiver.recv() => match message {
+                Some(message) => outbox.push(message),
+                None => return None,
+            },
+            _ = shutdown.changed() => return None,
+        }
+    }
+}
+
+/// Keeps the server connected to the router, publishing everything sent on `receiver`. If the
+/// connection fails or drops, it reconnects with backoff, joins the realm and registers the RPCs
+/// again, holding on to messages in the meantime (up to `max_buffered_messages`). Once `shutdown`
+/// is set, it sends whatever is still waiting (if it can) and disconnects.
+pub async fn run(
+    mut receiver: mpsc::UnboundedReceiver<Message>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let config = CONFIG.get().unwrap();
+    let mut backoff = Backoff::new(config.router.max_reconnect_delay);
+    let mut outbox = Outbox::new(config.limits.max_buffered_messages);
+
+    loop {
+        let connection = match buffer_until(
+            connect(&config.router),
+            &mut receiver,
+            &mut outbox,
+            &mut shutdown,
+        )
+        .await
+        {
+            Some(connection) => connection,
+            None => break,
+        };
+        let (mut client, mut event_loop) = match connection {
+            Ok(connection) => connection,
+            Err(err) => {
+                let delay = backoff.next_delay();
+                warn!(
+                    "Failed to connect to router ({:?}); retrying in {:?}",
+                    err, delay
+                );
+                let sleep = tokio::time::sleep(delay);
+                match buffer_until(sleep, &mut receiver, &mut outbox, &mut shutdown).await {
+                    Some(()) => continue,
+                    None => break,
+                }
+            }
+        };
+        backoff.reset();
+
+        match publish(
+            &client,
+            &mut event_loop,
+            &mut receiver,
+            &mut outbox,
+            &mut shutdown,
+        )
+        .await
+        {
+            Disconnect::Lost => {
+                warn!("Lost connection to router; reconnecting");
+                client.disconnect().await;
+            }
+            Disconnect::Shutdown => {
+                // Send anything that was queued up before shutdown started.
+                while let Ok(message) = receiver.try_recv() {
+                    outbox.push(message);
+                }
+                let timeout = config.limits.operation_timeout;
+                match tokio::time::timeout(timeout, outbox.flush(&client)).await {
+                    Ok(Ok(())) => info!("All messages sent"),
+                    _ => warn!(
+                        "Shutting down with {} messages unsent",
+                        outbox.messages.len()
+                    ),
+                }
+
+                if let Err(err) = client.leave_realm().await {
+                    warn!("Failed to leave realm: {:?}", err);
+                }
+                client.disconnect().await;
+                return;
+            }
+        }
+    }
+
+    warn!(
+        "Shutting down with {} messages unsent",
+        outbox.messages.len()
+    );
+}
+
+#[cfg(test)]
+mod connection_tests {
+    use std::time::Duration;
+
+    use super::{Backoff, Outbox};
+    use crate::Message;
+
+    fn message(topic: &'static str) -> Message {
+        Message {
+            topic: topic.into(),
+            args: None,
+            kwargs: None,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_limit() {
+        let mut backoff = Backoff::new(Duration::from_secs(5));
+        let delays = (0..5).map(|_| backoff.next_delay()).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [1, 2, 4, 5, 5]
+                .iter()
+                .map(|secs| Duration::from_secs(*secs))
+                .collect::<Vec<_>>()
+        );
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn outbox_drops_oldest_when_full() {
+        let mut outbox = Outbox::new(2);
+        outbox.push(message("a"));
+        outbox.push(message("b"));
+        outbox.push(message("c"));
+
+        assert_eq!(outbox.dropped, 1);
+        let topics = outbox
+            .messages
+            .iter()
+            .map(|message| message.topic.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(topics, ["b", "c"]);
+    }
+}
@@ -8,7 +8,8 @@ pub(crate) enum Error {
     DataUrlFormat(data_url::DataUrlError),
     DataUrlType,
     Io(std::io::Error),
-    LockTimeout,
+    Json(serde_json::Error),
+    Timeout,
     UnknownGame,
     BadArgument,
     InvalidStateForOperation,
@@ -20,6 +21,22 @@ pub(crate) enum Error {
     InvalidSquare,
     TooManyDailyDoubles,
     AvatarTooBig,
+    WrongPassword,
+    UnknownInviteCode,
+    Banned,
+    InvalidName,
+    NameTaken,
+    RateLimited,
+    TooManyGames,
+    TooManySpectators,
+    WaitingRoomFull,
+    NoHistory,
+    BadCredentials,
+    ProfileExists,
+    NotEligible,
+    LockedOut,
+    VersionConflict,
+    DatasetLoad,
 }
 impl From<wamp_async::WampError> for Error {
     fn from(value: WampError) -> Self {
@@ -41,6 +58,11 @@ impl From<std::io::Error> for Error {
         Error::Io(value)
     }
 }
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
 impl From<Error> for WampError {
     fn from(value: Error) -> WampError {
         use Error::*;
@@ -52,7 +74,11 @@ impl From<Error> for WampError {
                 DataUrlFormat(_) => "jpdy.data_url_format",
                 DataUrlType => "jpdy.avatar_data_type",
                 Io(_) => "jpdy.io_error",
-                LockTimeout => "jpdy.lock_timeout",
+                Json(err) => {
+                    log::warn!("JSON error: {}", err);
+                    "jpdy.json_error"
+                }
+                Timeout => "jpdy.timeout",
                 UnknownGame => "jpdy.unknown_error",
                 BadArgument => "jpdy.bad_argument",
                 InvalidStateForOperation => "jpdy.invalid_game_state",
@@ -65,6 +91,22 @@ impl From<Error> for WampError {
                 InvalidSquare => "jpdy.invalid_square",
                 TooManyDailyDoubles => "jpdy.too_many_daily_doubles",
                 AvatarTooBig => "jpdy.avatar_too_big",
+                WrongPassword => "jpdy.wrong_password",
+                UnknownInviteCode => "jpdy.unknown_invite_code",
+                Banned => "jpdy.banned",
+                InvalidName => "jpdy.invalid_name",
+                NameTaken => "jpdy.name_taken",
+                RateLimited => "jpdy.rate_limited",
+                TooManyGames => "jpdy.too_many_games",
+                TooManySpectators => "jpdy.too_many_spectators",
+                WaitingRoomFull => "jpdy.waiting_room_full",
+                NoHistory => "jpdy.no_history",
+                BadCredentials => "jpdy.bad_credentials",
+                ProfileExists => "jpdy.profile_exists",
+                NotEligible => "jpdy.not_eligible",
+                LockedOut => "jpdy.locked_out",
+                VersionConflict => "jpdy.version_conflict",
+                DatasetLoad => "jpdy.dataset_load_failed",
             }
             .into(),
         )
@@ -1,8 +1,10 @@
 use std::{convert::TryInto, fmt};
 
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use wamp_async::{WampKwArgs, WampPayloadValue};
 
+use super::events::SquareStatus;
 use crate::{errors::Error, seed::Seed};
 
 const CATEGORY_HEIGHT: usize = 5;
@@ -18,7 +20,7 @@ pub const DUMMY_BOARD: JeopardyBoard = JeopardyBoard {
 // Raw counts: 10, 433, 998, 1433, 945
 const DAILY_DOUBLE_WEIGHTS: [f64; 5] = [0.002, 0.113, 0.261, 0.375, 0.247];
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct Location {
     category: usize, // 0 is left, 4 is right
     row: usize,      // 0 is top, 4 is bottom
@@ -97,7 +99,7 @@ impl Location {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct JeopardyBoard {
     categories: Vec<Category>,
     pub value_multiplier: i64, // base values are "1, 2, 3, ..." going down a column
@@ -138,7 +140,54 @@ impl JeopardyBoard {
         self.value_multiplier * (1 + (location.row as i64))
     }
 
-    pub fn serialize(&self, for_moderator: bool, daily_double_entered: bool) -> WampKwArgs {
+    /// The value of the bottom row, which is the most any clue in this round is worth.
+    pub fn get_top_value(&self) -> i64 {
+        self.value_multiplier * (CATEGORY_HEIGHT as i64)
+    }
+
+    pub fn get_category_title(&self, location: &Location) -> &str {
+        &self.categories[location.category].title
+    }
+
+    pub fn get_category_titles(&self) -> Vec<String> {
+        self.categories
+            .iter()
+            .map(|cat| cat.title.clone())
+            .collect()
+    }
+
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_seed(&self) -> &Seed {
+        &self.seed
+    }
+
+    /// Every square which has been played (or at least selected), with its state.
+    pub fn get_square_statuses(&self) -> Vec<SquareStatus> {
+        let mut result = Vec::new();
+        for (category_index, category) in self.categories.iter().enumerate() {
+            for (row, square) in category.squares.iter().enumerate() {
+                if square.state != SquareState::Normal {
+                    result.push(SquareStatus {
+                        location: Location {
+                            category: category_index,
+                            row,
+                        },
+                        state: square.state.clone(),
+                    });
+                }
+            }
+        }
+        result
+    }
+
+    pub fn get_key(&self) -> (usize, usize) {
+        (self.id, self.etag)
+    }
+
+    pub fn serialize(&self, for_moderator: bool) -> WampKwArgs {
         let mut result = WampKwArgs::new();
 
         result.insert(
@@ -158,9 +207,7 @@ impl JeopardyBoard {
             WampPayloadValue::Array(
                 self.categories
                     .iter()
-                    .map(|cat| {
-                        WampPayloadValue::Object(cat.serialize(for_moderator, daily_double_entered))
-                    })
+                    .map(|cat| WampPayloadValue::Object(cat.serialize(for_moderator)))
                     .collect(),
             ),
         );
@@ -177,7 +224,7 @@ pub struct Category {
     pub squares: [Square; CATEGORY_HEIGHT],
 }
 impl Category {
-    fn serialize(&self, for_moderator: bool, daily_double_entered: bool) -> WampKwArgs {
+    fn serialize(&self, for_moderator: bool) -> WampKwArgs {
         let mut result = WampKwArgs::new();
 
         result.insert("title".into(), WampPayloadValue::String(self.title.clone()));
@@ -198,11 +245,7 @@ impl Category {
             WampPayloadValue::Array(
                 self.squares
                     .iter()
-                    .map(|square| {
-                        WampPayloadValue::Object(
-                            square.serialize(for_moderator, daily_double_entered),
-                        )
-                    })
+                    .map(|square| WampPayloadValue::Object(square.serialize(for_moderator)))
                     .collect(),
             ),
         );
@@ -228,7 +271,7 @@ impl Square {
         }
     }
 
-    fn serialize(&self, for_moderator: bool, daily_double_entered: bool) -> WampKwArgs {
+    fn serialize(&self, for_moderator: bool) -> WampKwArgs {
         let mut result = WampKwArgs::new();
         result.insert(
             "state".into(),
@@ -249,15 +292,24 @@ impl Square {
                 WampPayloadValue::Bool(self.is_daily_double),
             );
         } else {
-            match (&self.state, daily_double_entered) {
-                (SquareState::Normal, _) | (SquareState::DailyDoubleRevealed, false) => {}
-                (SquareState::Flipped, _) | (SquareState::DailyDoubleRevealed, true) => {
+            // Players only learn a square was a Daily Double once it's selected, and only see its
+            // clue once the moderator reveals it.
+            if !matches!(self.state, SquareState::Normal) {
+                result.insert(
+                    "is_daily_double".into(),
+                    WampPayloadValue::Bool(self.is_daily_double),
+                );
+            }
+
+            match self.state {
+                SquareState::Normal | SquareState::DailyDoubleRevealed => {}
+                SquareState::Flipped => {
                     result.insert(
                         "clue".into(),
                         WampPayloadValue::Object(self.clue.serialize()),
                     );
                 }
-                (SquareState::Finished, _) => {
+                SquareState::Finished => {
                     result.insert(
                         "clue".into(),
                         WampPayloadValue::Object(self.clue.serialize()),
@@ -288,6 +340,15 @@ impl Square {
         Ok(())
     }
 
+    pub(crate) fn show_daily_double_clue(&mut self) -> Result<(), Error> {
+        self.state = match self.state {
+            SquareState::DailyDoubleRevealed => SquareState::Flipped,
+            _ => return Err(Error::InvalidSquareStateTransition),
+        };
+
+        Ok(())
+    }
+
     pub(crate) fn finish(&mut self) -> Result<(), Error> {
         self.state = match self.state {
             SquareState::Flipped | SquareState::DailyDoubleRevealed => SquareState::Finished,
@@ -302,7 +363,7 @@ impl Square {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SquareState {
     Normal,
     DailyDoubleRevealed,
@@ -320,9 +381,11 @@ impl fmt::Display for SquareState {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Clue {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub link: Option<String>,
 }
 impl Clue {
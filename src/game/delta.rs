@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+
+use wamp_async::{WampKwArgs, WampPayloadValue};
+
+// Identifies a board state within a game, see `JeopardyBoard`
+pub type BoardKey = (usize, usize);
+
+// What was last published on one channel
+#[derive(Debug)]
+struct PublishedState {
+    version: u64,
+    fields: WampKwArgs, // everything but the board
+    board_key: Option<BoardKey>,
+}
+
+/// Remembers what was last published on each of a game's channels, so updates only need to carry
+/// what changed.
+///
+/// An update looks like `{"version", "base_version", "changed", "removed", "board"?}`: the
+/// top-level fields of the state which changed since `base_version` (or were removed), plus the
+/// board if it changed. `board` is null when the board was taken away (there's no board, or it's
+/// Final Jeopardy), and left out when it's unchanged. A client whose state isn't at
+/// `base_version` has missed an update, and should fetch a full snapshot with `jpdy.game_state`.
+#[derive(Debug, Default)]
+pub struct DeltaTracker {
+    published: HashMap<String, PublishedState>,
+}
+impl DeltaTracker {
+    /// Whether the channel needs to be sent a board with the given key.
+    pub fn needs_board(&self, channel: &str, board_key: Option<BoardKey>) -> bool {
+        board_key.is_some()
+            && self
+                .published
+                .get(channel)
+                .is_none_or(|published| published.board_key != board_key)
+    }
+
+    pub fn make_update(
+        &mut self,
+        channel: &str,
+        mut state: WampKwArgs,
+        board_key: Option<BoardKey>,
+        version: u64,
+    ) -> WampKwArgs {
+        // The board is tracked by its key rather than by comparing it
+        let board = match state.get_mut("state") {
+            Some(WampPayloadValue::Object(game_state)) => game_state.remove("board"),
+            _ => None,
+        };
+
+        let mut changed = WampKwArgs::new();
+        let mut removed = Vec::new();
+        let board_removed = board_key.is_none()
+            && self
+                .published
+                .get(channel)
+                .is_some_and(|published| published.board_key.is_some());
+        let base_version = match self.published.get(channel) {
+            Some(published) => {
+                for (key, value) in &state {
+                    if published.fields.get(key) != Some(value) {
+                        changed.insert(key.clone(), value.clone());
+                    }
+                }
+                for key in published.fields.keys() {
+                    if !state.contains_key(key) {
+                        removed.push(WampPayloadValue::String(key.clone()));
+                    }
+                }
+                WampPayloadValue::Number(published.version.into())
+            }
+            None => {
+                changed = state.clone();
+                WampPayloadValue::Null
+            }
+        };
+
+        let mut update = WampKwArgs::new();
+        update.insert("version".into(), WampPayloadValue::Number(version.into()));
+        update.insert("base_version".into(), base_version);
+        update.insert("changed".into(), WampPayloadValue::Object(changed));
+        update.insert("removed".into(), WampPayloadValue::Array(removed));
+        if let Some(board) = board {
+            update.insert("board".into(), board);
+        } else if board_removed {
+            update.insert("board".into(), WampPayloadValue::Null);
+        }
+
+        self.published.insert(
+            channel.to_string(),
+            PublishedState {
+                version,
+                fields: state,
+                board_key,
+            },
+        );
+        update
+    }
+
+    /// Forgets channels that are no longer in use (such as those of players who left).
+    pub fn retain(&mut self, channels: &HashSet<&str>) {
+        self.published
+            .retain(|channel, _| channels.contains(channel.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod delta_tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn only_changes_are_sent() {
+        let mut tracker = DeltaTracker::default();
+        let first = wamp_dict! {
+            "moderator" => "Alex".into(),
+            "invite_code" => "a-b-c".into(),
+        };
+        let update = tracker.make_update("chan", first, None, 1);
+        assert_eq!(update["base_version"], WampPayloadValue::Null);
+        assert_eq!(update["changed"].as_object().unwrap().len(), 2);
+
+        let second = wamp_dict! {
+            "moderator" => "Alex".into(),
+            "password" => "hunter2".into(),
+        };
+        let update = tracker.make_update("chan", second, None, 2);
+        assert_eq!(update["base_version"], WampPayloadValue::Number(1.into()));
+        let changed = update["changed"].as_object().unwrap();
+        assert_eq!(changed.len(), 1);
+        assert!(changed.contains_key("password"));
+        assert_eq!(
+            update["removed"],
+            WampPayloadValue::Array(vec![WampPayloadValue::String("invite_code".into())])
+        );
+
+        assert!(tracker.needs_board("chan", Some((1, 0))));
+        assert!(!tracker.needs_board("chan", None));
+    }
+
+    #[test]
+    fn board_removal_is_sent() {
+        let state =
+            |state: WampPayloadValue| json!({ "state": state }).as_object().unwrap().clone();
+        let mut tracker = DeltaTracker::default();
+
+        let with_board = state(json!({ "type": "WaitingForSquareSelection", "board": {} }));
+        let update = tracker.make_update("chan", with_board, Some((1, 0)), 1);
+        assert!(update["board"].is_object());
+        assert!(!tracker.needs_board("chan", Some((1, 0))));
+
+        // An unchanged board isn't sent again
+        let without_board = state(json!({ "type": "WaitingForSquareSelection" }));
+        let update = tracker.make_update("chan", without_board, Some((1, 0)), 2);
+        assert!(!update.contains_key("board"));
+
+        let update = tracker.make_update("chan", state(json!({ "type": "NoBoard" })), None, 3);
+        assert_eq!(update["board"], WampPayloadValue::Null);
+        let update = tracker.make_update("chan", state(json!({ "type": "NoBoard" })), None, 4);
+        assert!(!update.contains_key("board"));
+    }
+}
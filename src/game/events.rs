@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use wamp_async::{WampKwArgs, WampPayloadValue};
+
+use super::{
+    board::{Clue, Location, SquareState},
+    AnswerType, FinalJeopardyInfoType,
+};
+use crate::PlayerId;
+
+/// Something that happened during a game. Events which change scores record what the scores were
+/// afterwards, so that a game can be replayed from its log.
+///
+/// Clients and archives both get events in this serde form: `type` names the event, and its fields
+/// sit alongside it as plain JSON (numbers as numbers).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    PlayerJoined {
+        player: PlayerId,
+        name: String,
+    },
+    PlayerLeft {
+        player: PlayerId,
+        name: String,
+    },
+    PlayerKicked {
+        player: PlayerId,
+        banned: bool,
+    },
+    BoardLoaded {
+        board_id: usize,
+        seed: String,
+        value_multiplier: i64,
+        categories: Vec<String>,
+    },
+    SquareSelected {
+        location: Location,
+        category: String,
+        value: i64,
+        is_daily_double: bool,
+        clue: Clue,
+        answer: String,
+    },
+    BuzzerEnabled,
+    Buzz {
+        player: PlayerId,
+    },
+    // Someone buzzed in after another player won the race, which only counts towards their stats.
+    LateBuzz {
+        player: PlayerId,
+    },
+    DailyDoubleWager {
+        player: PlayerId,
+        wager: i64,
+    },
+    DailyDoubleClueRevealed {
+        location: Location,
+    },
+    AnswerJudged {
+        player: PlayerId,
+        judgment: AnswerType,
+        value: i64,
+        score: i64,
+    },
+    ClueSkipped {
+        location: Location,
+    },
+    FinalJeopardyStarted {
+        category: String,
+        clue: Clue,
+        answer: String,
+    },
+    FinalJeopardyWager {
+        player: PlayerId,
+        wager: i64,
+    },
+    FinalJeopardyQuestionRevealed,
+    FinalJeopardyAnswer {
+        player: PlayerId,
+        answer: String,
+    },
+    FinalJeopardyAnswersLocked,
+    FinalJeopardyEligibility {
+        eligible: Vec<PlayerId>,
+        ineligible: Vec<PlayerId>,
+    },
+    FinalJeopardyInfoRevealed {
+        player: PlayerId,
+        info_type: FinalJeopardyInfoType,
+    },
+    FinalJeopardyJudged {
+        player: PlayerId,
+        judgment: AnswerType,
+        wager: i64,
+        score: i64,
+    },
+    ScoreChanged {
+        player: PlayerId,
+        old_score: i64,
+        new_score: i64,
+    },
+    SquareStateChanged {
+        location: Location,
+        state: SquareState,
+    },
+    // Undoing and redoing can put anything back, so they record the board as well as the scores.
+    // Archives from before that have neither a board ID nor squares.
+    Undo {
+        scores: HashMap<PlayerId, i64>,
+        #[serde(default)]
+        board_id: Option<usize>,
+        #[serde(default)]
+        squares: Vec<SquareStatus>,
+    },
+    Redo {
+        scores: HashMap<PlayerId, i64>,
+        #[serde(default)]
+        board_id: Option<usize>,
+        #[serde(default)]
+        squares: Vec<SquareStatus>,
+    },
+    GameEnded,
+}
+impl GameEvent {
+    /// Whether this puts up a board or Final Jeopardy, which is what makes a game worth keeping.
+    /// Players joining and leaving alone don't count.
+    pub fn starts_play(&self) -> bool {
+        matches!(
+            self,
+            GameEvent::BoardLoaded { .. } | GameEvent::FinalJeopardyStarted { .. }
+        )
+    }
+}
+
+/// The state of one square on a board. Squares which haven't been played yet are left out of lists
+/// of these.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SquareStatus {
+    pub location: Location,
+    pub state: SquareState,
+}
+
+/// An event along with where and when it happened in the game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    pub index: usize,
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+impl LoggedEvent {
+    pub fn serialize(&self) -> WampKwArgs {
+        match serde_json::to_value(self) {
+            Ok(WampPayloadValue::Object(result)) => result,
+            _ => unreachable!("events always serialize to objects"),
+        }
+    }
+}
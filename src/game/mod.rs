@@ -1,26 +1,63 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use log::*;
 use rand::{seq::IteratorRandom, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 use wamp_async::{WampKwArgs, WampPayloadValue};
 
 use crate::{
-    data::FinalJeopardyQuestion, errors::Error, seed::Seed, AuthToken, PlayerId, JEOPARDY_DATA,
+    archive::{Transcript, TranscriptPlayer},
+    data::FinalJeopardyQuestion,
+    errors::Error,
+    gc::{self, Expiry, GcConfig},
+    names,
+    profiles::GameResult,
+    seed::Seed,
+    AuthToken, GameId, InviteCode, PlayerId, JEOPARDY_DATA,
 };
 
 const MIN_DAILY_DOUBLE_WAGER: i64 = 5;
-const MIN_MAX_DAILY_DOUBLE_WAGER_FACTOR: i64 = 50;
+const MAX_UNDO_HISTORY: usize = 20;
+const MAX_SPECTATORS: usize = 200;
+// Everyone waiting is sent to everyone else on every update, so the line can't grow forever.
+const MAX_WAITING_PLAYERS: usize = 50;
 
 pub mod board;
+mod delta;
+pub mod events;
+pub mod stats;
 use board::*;
+use delta::DeltaTracker;
+use events::{GameEvent, LoggedEvent, SquareStatus};
+use stats::PlayerStats;
+
+/// Compares two byte strings in time that only depends on their length, so how long a wrong
+/// password takes to reject doesn't say how close it was.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AnswerType {
     Correct,
     Incorrect,
     Skip,
 }
+impl fmt::Display for AnswerType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnswerType::Correct => write!(fmt, "Correct"),
+            AnswerType::Incorrect => write!(fmt, "Incorrect"),
+            AnswerType::Skip => write!(fmt, "Skip"),
+        }
+    }
+}
 impl std::str::FromStr for AnswerType {
     type Err = ();
 
@@ -38,16 +75,97 @@ impl std::str::FromStr for AnswerType {
 pub enum PlayerType {
     Moderator,
     Player,
+    Waiting, // Has asked to join, but doesn't have a seat yet
+}
+
+/// What happened to a request to join a game.
+#[derive(Debug, Clone, Copy)]
+pub enum JoinStatus {
+    Joined,
+    Waiting,         // The game is full; the player will be seated when someone leaves
+    PendingApproval, // The moderator needs to approve the player first
+}
+impl fmt::Display for JoinStatus {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinStatus::Joined => write!(fmt, "Joined"),
+            JoinStatus::Waiting => write!(fmt, "Waiting"),
+            JoinStatus::PendingApproval => write!(fmt, "PendingApproval"),
+        }
+    }
+}
+
+// Someone who has asked to join a game but isn't playing yet.
+#[derive(Debug)]
+struct WaitingPlayer {
+    id: PlayerId,
+    player: Player,
+    approved: bool,
+}
+impl WaitingPlayer {
+    fn serialize(&self) -> WampKwArgs {
+        let mut result = WampKwArgs::new();
+        result.insert("id".into(), WampPayloadValue::String(self.id.to_string()));
+        result.insert(
+            "name".into(),
+            WampPayloadValue::String(self.player.name.clone()),
+        );
+        result.insert(
+            "avatar_url".into(),
+            WampPayloadValue::String(self.player.avatar_url.clone()),
+        );
+        result.insert("approved".into(), WampPayloadValue::Bool(self.approved));
+        result
+    }
 }
 
 // Final Jeopardy state for one player
 #[derive(Debug, Default, Clone)]
 struct FinalJeopardyInfo {
+    eligible: bool, // only players with a positive score can play
     wager: Option<i64>,
     answer: Option<String>,
+    judgment: Option<AnswerType>,
     wager_revealed: bool,
     answer_revealed: bool,
 }
+impl FinalJeopardyInfo {
+    // What the player has handed in, so they can see it was received
+    fn serialize_submission(&self) -> WampKwArgs {
+        let mut result = WampKwArgs::new();
+        result.insert("eligible".into(), WampPayloadValue::Bool(self.eligible));
+        result.insert(
+            "wager".into(),
+            match self.wager {
+                Some(wager) => WampPayloadValue::String(wager.to_string()),
+                None => WampPayloadValue::Null,
+            },
+        );
+        result.insert(
+            "answer".into(),
+            match self.answer {
+                Some(ref answer) => WampPayloadValue::String(answer.clone()),
+                None => WampPayloadValue::Null,
+            },
+        );
+        result
+    }
+
+    // Whether the player has nothing left to hand in
+    fn is_complete(&self) -> bool {
+        !self.eligible || (self.wager.is_some() && self.answer.is_some())
+    }
+
+    // How much a judgment changes the player's score
+    fn score_change(&self, judgment: Option<AnswerType>) -> i64 {
+        let wager = self.wager.unwrap_or(0);
+        match judgment {
+            Some(AnswerType::Correct) => wager,
+            Some(AnswerType::Incorrect) => -wager,
+            Some(AnswerType::Skip) | None => 0,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Player {
@@ -56,6 +174,9 @@ pub struct Player {
     auth: AuthToken,
     avatar_url: String,
     final_jeopardy_info: FinalJeopardyInfo,
+    stats: PlayerStats,
+    profile: Option<String>, // key of the player's profile, if they signed in
+    channel: String,         // where this player's own view of the game is published
 }
 impl Player {
     pub fn new(name: String, avatar_url: String) -> Self {
@@ -65,9 +186,20 @@ impl Player {
             auth: AuthToken(Uuid::new_v4()),
             avatar_url,
             final_jeopardy_info: Default::default(),
+            stats: Default::default(),
+            profile: None,
+            channel: format!("jpdy.chan.{}", Uuid::new_v4().hyphenated()),
         }
     }
 
+    pub fn get_channel(&self) -> &str {
+        &self.channel
+    }
+
+    pub fn set_profile(&mut self, profile: Option<String>) {
+        self.profile = profile;
+    }
+
     pub fn get_auth(&self) -> AuthToken {
         self.auth.clone()
     }
@@ -92,6 +224,11 @@ impl Player {
             "final_jeopardy_info".into(),
             WampPayloadValue::Object({
                 let mut player_result = WampKwArgs::default();
+                player_result.insert(
+                    "eligible".into(),
+                    WampPayloadValue::Bool(self.final_jeopardy_info.eligible),
+                );
+
                 if self.final_jeopardy_info.wager_revealed || for_moderator {
                     match self.final_jeopardy_info.wager {
                         Some(ref wager) => {
@@ -118,6 +255,15 @@ impl Player {
                     }
                 }
 
+                if self.final_jeopardy_info.answer_revealed || for_moderator {
+                    if let Some(judgment) = self.final_jeopardy_info.judgment {
+                        player_result.insert(
+                            "judgment".into(),
+                            WampPayloadValue::String(judgment.to_string()),
+                        );
+                    }
+                }
+
                 if for_moderator {
                     player_result.insert(
                         "wager_revealed".into(),
@@ -133,11 +279,18 @@ impl Player {
             }),
         );
 
+        if for_moderator {
+            result.insert(
+                "stats".into(),
+                WampPayloadValue::Object(self.stats.serialize()),
+            );
+        }
+
         result
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum FinalJeopardyInfoType {
     Wager,
     Answer,
@@ -154,7 +307,25 @@ impl std::str::FromStr for FinalJeopardyInfoType {
     }
 }
 
-#[derive(Debug)]
+/// How far along a Daily Double is. The clue stays hidden until the moderator reveals it, which
+/// they can only do once the wager is locked in.
+#[derive(Debug, Clone, Copy)]
+enum DailyDoubleStage {
+    Revealed,
+    WagerSubmitted { wager: i64 },
+    ClueShown { wager: i64 },
+}
+impl fmt::Display for DailyDoubleStage {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DailyDoubleStage::Revealed => write!(fmt, "Revealed"),
+            DailyDoubleStage::WagerSubmitted { .. } => write!(fmt, "WagerSubmitted"),
+            DailyDoubleStage::ClueShown { .. } => write!(fmt, "ClueShown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 enum GameState {
     NoBoard,
     WaitingForSquareSelection {
@@ -167,10 +338,11 @@ enum GameState {
         location: Location,
         controller: PlayerId,
     },
-    WaitingForDailyDoubleWager {
+    DailyDouble {
         board: Box<JeopardyBoard>,
         location: Location,
-        controller: PlayerId, // ID of whoever's making the wager
+        controller: PlayerId, // ID of whoever found the Daily Double, and is the only one playing it
+        stage: DailyDoubleStage,
     },
     WaitingForBuzzer {
         board: Box<JeopardyBoard>,
@@ -181,7 +353,7 @@ enum GameState {
         board: Box<JeopardyBoard>,
         location: Location,
         controller: PlayerId,    // ID of whoever's controlling the board
-        active_player: PlayerId, // ID of whoever won the buzzer race or is doing the daily double
+        active_player: PlayerId, // ID of whoever won the buzzer race
         value: i64,              // Value added to score if correct, or subtracted if wrong
     },
     FinalJeopardy {
@@ -191,21 +363,37 @@ enum GameState {
         answer: String,
         question_revealed: bool,
         answers_locked: bool,
+        reveal_order: Vec<PlayerId>, // eligible players, lowest score going in first
+        next_reveal: usize,          // index into `reveal_order` of whoever's being revealed
     },
 }
 impl GameState {
+    fn get_board(&self) -> Option<&JeopardyBoard> {
+        match self {
+            GameState::WaitingForSquareSelection { board, .. }
+            | GameState::WaitingForEnableBuzzer { board, .. }
+            | GameState::DailyDouble { board, .. }
+            | GameState::WaitingForBuzzer { board, .. }
+            | GameState::WaitingForAnswer { board, .. } => Some(board),
+            GameState::NoBoard | GameState::FinalJeopardy { .. } => None,
+        }
+    }
+
     fn serialize_helper(
         &self,
         result: &mut WampKwArgs,
         board: &JeopardyBoard,
         controller: Option<&PlayerId>,
         for_moderator: bool,
+        include_board: bool,
     ) {
-        let daily_double_entered = !matches!(self, GameState::WaitingForDailyDoubleWager { .. });
-        result.insert(
-            "board".into(),
-            WampPayloadValue::Object(board.serialize(for_moderator, daily_double_entered)),
-        );
+        // Boards are big, so updates only include them when they've changed
+        if include_board {
+            result.insert(
+                "board".into(),
+                WampPayloadValue::Object(board.serialize(for_moderator)),
+            );
+        }
         if let Some(player_id) = controller {
             result.insert(
                 "controller".into(),
@@ -221,15 +409,16 @@ impl GameState {
         controller: Option<&PlayerId>,
         location: &Location,
         for_moderator: bool,
+        include_board: bool,
     ) {
-        self.serialize_helper(result, board, controller, for_moderator);
+        self.serialize_helper(result, board, controller, for_moderator, include_board);
         result.insert(
             "location".into(),
             WampPayloadValue::Object(location.serialize()),
         );
     }
 
-    fn serialize(&self, for_moderator: bool) -> WampKwArgs {
+    fn serialize(&self, for_moderator: bool, include_board: bool) -> WampKwArgs {
         let mut result = WampKwArgs::new();
 
         match self {
@@ -242,7 +431,13 @@ impl GameState {
                     "type".into(),
                     WampPayloadValue::String("WaitingForSquareSelection".into()),
                 );
-                self.serialize_helper(&mut result, board, controller.as_ref(), for_moderator);
+                self.serialize_helper(
+                    &mut result,
+                    board,
+                    controller.as_ref(),
+                    for_moderator,
+                    include_board,
+                );
             }
 
             GameState::WaitingForEnableBuzzer { board, controller, location } => {
@@ -256,17 +451,19 @@ impl GameState {
                     Some(controller),
                     location,
                     for_moderator,
+                    include_board,
                 );
             }
 
-            GameState::WaitingForDailyDoubleWager {
+            GameState::DailyDouble {
                 board,
                 controller,
                 location,
+                stage,
             } => {
                 result.insert(
                     "type".into(),
-                    WampPayloadValue::String("WaitingForDailyDoubleWager".into()),
+                    WampPayloadValue::String("DailyDouble".into()),
                 );
                 self.serialize_helper2(
                     &mut result,
@@ -274,7 +471,21 @@ impl GameState {
                     Some(controller),
                     location,
                     for_moderator,
+                    include_board,
                 );
+                result.insert("stage".into(), WampPayloadValue::String(stage.to_string()));
+
+                // The moderator reads the wager out before revealing the clue
+                match stage {
+                    DailyDoubleStage::Revealed => {}
+                    DailyDoubleStage::WagerSubmitted { wager } if for_moderator => {
+                        result.insert("wager".into(), WampPayloadValue::String(wager.to_string()));
+                    }
+                    DailyDoubleStage::WagerSubmitted { .. } => {}
+                    DailyDoubleStage::ClueShown { wager } => {
+                        result.insert("wager".into(), WampPayloadValue::String(wager.to_string()));
+                    }
+                }
             }
 
             GameState::WaitingForBuzzer {
@@ -292,6 +503,7 @@ impl GameState {
                     Some(controller),
                     location,
                     for_moderator,
+                    include_board,
                 );
             }
 
@@ -312,6 +524,7 @@ impl GameState {
                     Some(controller),
                     location,
                     for_moderator,
+                    include_board,
                 );
                 result.insert(
                     "active_player".into(),
@@ -326,8 +539,28 @@ impl GameState {
                 answer,
                 question_revealed,
                 answers_locked,
+                reveal_order,
+                next_reveal,
             } => {
                 result.insert("type".into(), WampPayloadValue::String("FinalJeopardy".into()));
+                result.insert(
+                    "reveal_order".into(),
+                    WampPayloadValue::Array(
+                        reveal_order
+                            .iter()
+                            .map(|player_id| WampPayloadValue::String(player_id.to_string()))
+                            .collect(),
+                    ),
+                );
+                result.insert(
+                    "current_reveal".into(),
+                    match reveal_order.get(*next_reveal) {
+                        Some(player_id) if *answers_locked => {
+                            WampPayloadValue::String(player_id.to_string())
+                        }
+                        _ => WampPayloadValue::Null,
+                    },
+                );
                 result.insert("category".into(), WampPayloadValue::String(category_name.clone()));
                 result.insert("air_year".into(), WampPayloadValue::Number((*air_year).into()));
                 result.insert("answers_locked".into(), WampPayloadValue::Bool(*answers_locked));
@@ -347,37 +580,261 @@ impl GameState {
     }
 }
 
+// A copy of everything moderator actions can change, used to undo and redo them.
+#[derive(Debug)]
+struct Snapshot {
+    state: GameState,
+    next_board_id: usize,
+    players: HashMap<PlayerId, Player>,
+    locked_out: HashSet<PlayerId>,
+    buzzed: HashSet<PlayerId>,
+}
+
 #[derive(Debug)]
 pub struct Game {
+    pub id: GameId,
     pub moderator_id: PlayerId,
     moderator: Player,
     players: HashMap<PlayerId, Player>,
+    waiting_room: VecDeque<WaitingPlayer>, // in the order people asked to join
     state: GameState,
     next_board_id: usize,
 
     pub time_started: DateTime<Utc>,
+    last_activity: DateTime<Utc>, // games nobody has touched in a while get cleaned up
+    expiry_warning_sent: bool,
     pub moderator_state_channel: String,
     pub player_state_channel: String,
     pub is_ended: bool,
+
+    pub invite_code: InviteCode,
+    pub is_private: bool, // private games aren't listed in the lobby
+    password: Option<(String, Vec<u8>)>, // salt and hash
+
+    max_players: Option<usize>,
+    approve_joins: bool, // if set, the moderator has to approve everyone who asks to join
+
+    // Channel for one-off notices (like being kicked) rather than state updates.
+    pub notification_channel: String,
+    // Avatar URLs are named after the hash of the image, so they identify a returning player even
+    // if they change their name. Anyone signed in is banned by profile too, since they can just
+    // pick a new avatar.
+    banned_avatars: HashSet<String>,
+    banned_profiles: HashSet<String>,
+
+    undo_history: VecDeque<Snapshot>, // oldest first
+    redo_history: Vec<Snapshot>,
+
+    // Everything that's happened so far, which the moderator can follow on the event channel.
+    event_log: Vec<LoggedEvent>,
+    published_event_count: usize,
+    pub event_channel: String,
+
+    // Players who already answered the current clue wrong and can't buzz in again
+    locked_out: HashSet<PlayerId>,
+    // Players whose buzz on the current clue has been counted in their stats
+    buzzed: HashSet<PlayerId>,
+
+    // People following the game on the player channel without playing, and when each last checked
+    // in. Only counted for the lobby.
+    spectators: HashMap<PlayerId, DateTime<Utc>>,
+
+    version: u64, // bumped by every change, so clients can say which state they're acting on
+    delta: DeltaTracker,
 }
 impl Game {
-    pub(crate) fn new(moderator: Player) -> Self {
+    pub(crate) fn new(id: GameId, moderator: Player, invite_code: InviteCode) -> Self {
         let moderator_id = PlayerId(Uuid::new_v4());
 
         Game {
+            id,
             moderator_id,
             moderator,
             players: HashMap::new(),
+            waiting_room: VecDeque::new(),
             state: GameState::NoBoard,
             next_board_id: 0,
 
             time_started: Utc::now(),
+            last_activity: Utc::now(),
+            expiry_warning_sent: false,
             moderator_state_channel: format!("jpdy.chan.{}", Uuid::new_v4().hyphenated()),
             player_state_channel: format!("jpdy.chan.{}", Uuid::new_v4().hyphenated()),
             is_ended: false,
+
+            invite_code,
+            is_private: false,
+            password: None,
+
+            max_players: None,
+            approve_joins: false,
+
+            notification_channel: format!("jpdy.chan.{}", Uuid::new_v4().hyphenated()),
+            banned_avatars: HashSet::new(),
+            banned_profiles: HashSet::new(),
+
+            undo_history: VecDeque::new(),
+            redo_history: Vec::new(),
+
+            event_log: Vec::new(),
+            published_event_count: 0,
+            event_channel: format!("jpdy.chan.{}", Uuid::new_v4().hyphenated()),
+
+            locked_out: HashSet::new(),
+            buzzed: HashSet::new(),
+
+            spectators: HashMap::new(),
+
+            version: 0,
+            delta: Default::default(),
+        }
+    }
+
+    // Most changes to the game log an event, so this is also where the version usually moves on.
+    fn log_event(&mut self, event: GameEvent) {
+        debug!("Game event: {:?}", event);
+        self.mark_changed();
+        self.event_log.push(LoggedEvent {
+            index: self.event_log.len(),
+            time: Utc::now(),
+            event,
+        });
+    }
+
+    // Moves the version on, for changes that clients need to see (even ones that don't log an
+    // event, like the waiting room or the password changing).
+    fn mark_changed(&mut self) {
+        self.version += 1;
+        self.touch();
+    }
+
+    fn touch(&mut self) {
+        self.last_activity = Utc::now();
+        self.expiry_warning_sent = false;
+    }
+
+    /// Works out whether the garbage collector should remove the game, or warn everyone that it's
+    /// about to. Each idle spell only gets one warning.
+    pub(crate) fn check_expiry(&mut self, now: DateTime<Utc>, config: &GcConfig) -> Expiry {
+        match gc::get_expiry(self.last_activity, now, config) {
+            Expiry::Warn { .. } if self.expiry_warning_sent => Expiry::Active,
+            expiry @ Expiry::Warn { .. } => {
+                self.expiry_warning_sent = true;
+                expiry
+            }
+            expiry => expiry,
+        }
+    }
+
+    /// Gets the logged events starting at the given index.
+    pub(crate) fn get_events_since(&self, index: usize) -> &[LoggedEvent] {
+        self.event_log.get(index..).unwrap_or(&[])
+    }
+
+    /// Gets the events which haven't been published on the event channel yet, and marks them as
+    /// published.
+    pub(crate) fn take_unpublished_events(&mut self) -> Vec<LoggedEvent> {
+        let events = self.event_log[self.published_event_count..].to_vec();
+        self.published_event_count = self.event_log.len();
+        events
+    }
+
+    pub(crate) fn make_transcript(&self, game_id: &GameId) -> Transcript {
+        Transcript {
+            game_id: game_id.clone(),
+            moderator: self.moderator.name.clone(),
+            time_started: self.time_started,
+            time_ended: Utc::now(),
+            players: self
+                .players
+                .iter()
+                .map(|(player_id, player)| TranscriptPlayer {
+                    id: player_id.clone(),
+                    name: player.name.clone(),
+                    avatar_url: player.avatar_url.clone(),
+                    final_score: player.score,
+                })
+                .collect(),
+            events: self.event_log.clone(),
+            invite_code: Some(self.invite_code.to_string()),
         }
     }
 
+    /// Results for every player who signed in with a profile. Whoever has the highest score wins
+    /// (everyone tied for first counts as a winner).
+    pub(crate) fn get_profile_results(&self) -> Vec<GameResult> {
+        let high_score = self.players.values().map(|player| player.score).max();
+
+        self.players
+            .values()
+            .filter_map(|player| {
+                Some(GameResult {
+                    profile: player.profile.clone()?,
+                    score: player.score,
+                    won: Some(player.score) == high_score,
+                    correct: player.stats.get_correct(),
+                    incorrect: player.stats.get_incorrect(),
+                    coryat: player.stats.get_coryat(),
+                })
+            })
+            .collect()
+    }
+
+    /// Whether a board or Final Jeopardy was ever put up.
+    pub(crate) fn got_going(&self) -> bool {
+        self.event_log
+            .iter()
+            .any(|logged| logged.event.starts_play())
+    }
+
+    pub(crate) fn end(&mut self) {
+        self.is_ended = true;
+        self.log_event(GameEvent::GameEnded);
+    }
+
+    fn hash_password(salt: &str, password: &str) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(salt.as_bytes());
+        hasher.update(password.as_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// Sets the password needed to join the game. An empty password (or none at all) removes it.
+    pub(crate) fn set_password(&mut self, password: Option<&str>) {
+        self.password = match password {
+            Some(password) if !password.is_empty() => {
+                let salt = Uuid::new_v4().to_string();
+                let hash = Game::hash_password(&salt, password);
+                Some((salt, hash))
+            }
+            _ => None,
+        };
+        self.mark_changed();
+    }
+
+    pub(crate) fn set_private(&mut self, is_private: bool) {
+        self.is_private = is_private;
+        self.mark_changed();
+    }
+
+    pub(crate) fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    pub(crate) fn check_password(&self, password: Option<&str>) -> bool {
+        match (&self.password, password) {
+            (None, _) => true,
+            (Some((salt, hash)), Some(password)) => {
+                constant_time_eq(hash, &Game::hash_password(salt, password))
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    pub(crate) fn is_spectator(&self, id: &PlayerId) -> bool {
+        self.spectators.contains_key(id)
+    }
+
     pub(crate) fn auth_and_get_player_type(
         &self,
         id: &PlayerId,
@@ -387,8 +844,17 @@ impl Game {
             return Some(PlayerType::Moderator);
         }
 
-        if self.players.get(id)?.check_auth(auth) {
-            return Some(PlayerType::Player);
+        if let Some(player) = self.players.get(id) {
+            return if player.check_auth(auth) {
+                Some(PlayerType::Player)
+            } else {
+                None
+            };
+        }
+
+        let waiting = self.waiting_room.iter().find(|waiting| waiting.id == *id)?;
+        if waiting.player.check_auth(auth) {
+            return Some(PlayerType::Waiting);
         }
 
         None
@@ -409,9 +875,207 @@ impl Game {
             .collect()
     }
 
+    /// Serializes the game's entry in the lobby, or `None` if it shouldn't be listed there.
+    pub(crate) fn serialize_listing(&self) -> Option<WampKwArgs> {
+        if self.is_private {
+            return None;
+        }
+        Some(self.serialize_summary())
+    }
+
+    /// Serializes everything an operator might want to know about the game, whether or not it's
+    /// listed in the lobby. Players are included with their IDs, so they can be kicked.
+    pub(crate) fn serialize_admin_summary(&self, game_id: &GameId) -> WampKwArgs {
+        let mut dict = self.serialize_summary();
+        dict.insert(
+            "game_id".to_string(),
+            WampPayloadValue::String(game_id.to_string()),
+        );
+        dict.insert(
+            "invite_code".to_string(),
+            WampPayloadValue::String(self.invite_code.to_string()),
+        );
+        dict.insert(
+            "is_private".to_string(),
+            WampPayloadValue::Bool(self.is_private),
+        );
+        dict.insert(
+            "player_ids".to_string(),
+            WampPayloadValue::Array(
+                self.players
+                    .iter()
+                    .map(|(id, player)| {
+                        WampPayloadValue::Object(wamp_dict! {
+                            "player_id" => id.to_string(),
+                            "name" => player.name.clone(),
+                            "score" => player.score.to_string(),
+                        })
+                    })
+                    .collect(),
+            ),
+        );
+        dict.insert(
+            "waiting_count".to_string(),
+            WampPayloadValue::Number(self.waiting_room.len().into()),
+        );
+        dict.insert(
+            "event_count".to_string(),
+            WampPayloadValue::Number(self.event_log.len().into()),
+        );
+        dict.insert(
+            "version".to_string(),
+            WampPayloadValue::Number(self.version.into()),
+        );
+        dict.insert(
+            "last_activity".to_string(),
+            WampPayloadValue::String(self.last_activity.to_rfc3339()),
+        );
+        dict
+    }
+
+    /// Every avatar someone in the game (seated, waiting or moderating) is using.
+    pub(crate) fn get_avatar_urls(&self) -> Vec<String> {
+        std::iter::once(&self.moderator)
+            .chain(self.players.values())
+            .chain(self.waiting_room.iter().map(|waiting| &waiting.player))
+            .map(|player| player.avatar_url.clone())
+            .collect()
+    }
+
+    fn serialize_summary(&self) -> WampKwArgs {
+        let mut dict = wamp_dict! {
+            "moderator" => self.get_moderator_name().into(),
+            "moderator_avatar" => self.get_moderator_avatar_url().into(),
+        };
+        let players = WampPayloadValue::Array(
+            self.get_player_names()
+                .iter()
+                .map(|name| WampPayloadValue::String((*name).to_string()))
+                .collect(),
+        );
+        dict.insert("players".to_string(), players);
+        dict.insert(
+            "player_count".to_string(),
+            WampPayloadValue::Number(self.players.len().into()),
+        );
+        dict.insert(
+            "max_players".to_string(),
+            match self.max_players {
+                Some(max_players) => WampPayloadValue::Number(max_players.into()),
+                None => WampPayloadValue::Null,
+            },
+        );
+        dict.insert(
+            "spectator_count".to_string(),
+            WampPayloadValue::Number(self.spectators.len().into()),
+        );
+        dict.insert(
+            "phase".to_string(),
+            WampPayloadValue::String(self.get_phase().to_string()),
+        );
+        // Boards are numbered from 1, so this is also the number of the one being played, if any.
+        dict.insert(
+            "board_number".to_string(),
+            WampPayloadValue::Number(self.next_board_id.into()),
+        );
+        dict.insert(
+            "time_started".to_string(),
+            WampPayloadValue::String(self.time_started.to_rfc3339()),
+        );
+        dict.insert(
+            "has_password".to_string(),
+            WampPayloadValue::Bool(self.has_password()),
+        );
+        // Whether someone asking to join now would be seated right away
+        dict.insert(
+            "joinable".to_string(),
+            WampPayloadValue::Bool(!self.is_ended && !self.approve_joins && !self.is_full()),
+        );
+        dict.insert(
+            "approve_joins".to_string(),
+            WampPayloadValue::Bool(self.approve_joins),
+        );
+
+        dict
+    }
+
+    /// How far along the game is, for the lobby.
+    fn get_phase(&self) -> &'static str {
+        if self.is_ended {
+            return "Ended";
+        }
+        match self.state {
+            GameState::NoBoard => "NotStarted",
+            GameState::FinalJeopardy { .. } => "FinalJeopardy",
+            _ => "Round",
+        }
+    }
+
+    /// Starts counting someone as watching the game, returning the ID they stop watching with.
+    pub(crate) fn add_spectator(&mut self) -> Result<PlayerId, Error> {
+        if self.spectators.len() >= MAX_SPECTATORS {
+            return Err(Error::TooManySpectators);
+        }
+
+        let id = PlayerId(Uuid::new_v4());
+        self.spectators.insert(id.clone(), Utc::now());
+        self.mark_changed();
+        Ok(id)
+    }
+
+    pub(crate) fn remove_spectator(&mut self, id: &PlayerId) -> bool {
+        let removed = self.spectators.remove(id).is_some();
+        if removed {
+            self.mark_changed();
+        }
+        removed
+    }
+
+    /// Notes that a spectator is still watching. Returns false if they aren't counted any more.
+    pub(crate) fn refresh_spectator(&mut self, id: &PlayerId) -> bool {
+        match self.spectators.get_mut(id) {
+            Some(last_seen) => {
+                *last_seen = Utc::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops counting spectators who haven't checked in for `timeout`, since closing the page
+    /// doesn't tell the server they've gone. Returns how many were dropped.
+    pub(crate) fn prune_spectators(&mut self, now: DateTime<Utc>, timeout: Duration) -> usize {
+        let count = self.spectators.len();
+        self.spectators
+            .retain(|_, last_seen| now.signed_duration_since(*last_seen) < timeout);
+
+        let removed = count - self.spectators.len();
+        if removed > 0 {
+            // Not activity, so this doesn't hold off the garbage collector
+            self.version += 1;
+        }
+        removed
+    }
+
+    pub(crate) fn get_spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    pub(crate) fn get_version(&self) -> u64 {
+        self.version
+    }
+
     pub fn serialize(&self, for_moderator: bool) -> WampKwArgs {
+        self.serialize_view(for_moderator, true)
+    }
+
+    fn serialize_view(&self, for_moderator: bool, include_board: bool) -> WampKwArgs {
         let mut result = WampKwArgs::new();
 
+        result.insert(
+            "version".into(),
+            WampPayloadValue::Number(self.version.into()),
+        );
         result.insert("is_ended".into(), WampPayloadValue::Bool(self.is_ended));
         result.insert(
             "players".into(),
@@ -428,31 +1092,540 @@ impl Game {
             ),
         );
 
-        result.insert(
-            "state".into(),
-            WampPayloadValue::Object(self.state.serialize(for_moderator)),
-        );
+        result.insert(
+            "waiting_room".into(),
+            WampPayloadValue::Array(
+                self.waiting_room
+                    .iter()
+                    .map(|waiting| WampPayloadValue::Object(waiting.serialize()))
+                    .collect(),
+            ),
+        );
+        result.insert(
+            "max_players".into(),
+            match self.max_players {
+                Some(max_players) => WampPayloadValue::Number(max_players.into()),
+                None => WampPayloadValue::Null,
+            },
+        );
+        result.insert(
+            "approve_joins".into(),
+            WampPayloadValue::Bool(self.approve_joins),
+        );
+
+        let mut state = self.state.serialize(for_moderator, include_board);
+        if for_moderator {
+            if let Some((min_wager, max_wager)) = self.get_daily_double_wager_range() {
+                state.insert(
+                    "wager_range".into(),
+                    WampPayloadValue::Object(wamp_dict! {
+                        "min" => min_wager.to_string(),
+                        "max" => max_wager.to_string(),
+                    }),
+                );
+            }
+            state.insert(
+                "locked_out".into(),
+                WampPayloadValue::Array(
+                    self.locked_out
+                        .iter()
+                        .map(|player_id| WampPayloadValue::String(player_id.to_string()))
+                        .collect(),
+                ),
+            );
+            if matches!(self.state, GameState::FinalJeopardy { .. }) {
+                self.serialize_submission_checklist(&mut state);
+            }
+        }
+        result.insert("state".into(), WampPayloadValue::Object(state));
+
+        result.insert("is_moderator".into(), WampPayloadValue::Bool(for_moderator));
+        result.insert(
+            "notification_channel".into(),
+            WampPayloadValue::String(self.notification_channel.clone()),
+        );
+        if for_moderator {
+            result.insert(
+                "invite_code".into(),
+                WampPayloadValue::String(self.invite_code.to_string()),
+            );
+            result.insert(
+                "can_undo".into(),
+                WampPayloadValue::Bool(!self.undo_history.is_empty()),
+            );
+            result.insert(
+                "can_redo".into(),
+                WampPayloadValue::Bool(!self.redo_history.is_empty()),
+            );
+            result.insert(
+                "event_channel".into(),
+                WampPayloadValue::String(self.event_channel.clone()),
+            );
+            result.insert("is_private".into(), WampPayloadValue::Bool(self.is_private));
+            result.insert(
+                "has_password".into(),
+                WampPayloadValue::Bool(self.has_password()),
+            );
+        }
+        if self.is_ended {
+            result.insert(
+                "summary".into(),
+                WampPayloadValue::Array(self.make_summary()),
+            );
+        }
+        result.insert(
+            "moderator".into(),
+            WampPayloadValue::String(self.moderator.name.clone()),
+        );
+        result.insert(
+            "min_year".into(),
+            WampPayloadValue::Number(JEOPARDY_DATA.read().min_year.into()),
+        );
+        result.insert(
+            "max_year".into(),
+            WampPayloadValue::Number(JEOPARDY_DATA.read().max_year.into()),
+        );
+
+        result
+    }
+
+    /// The state as seen by one player, including the Final Jeopardy wager and answer they've
+    /// submitted (which are hidden from everyone else until they're revealed).
+    pub fn serialize_for_player(&self, player_id: &PlayerId) -> WampKwArgs {
+        self.serialize_player_view(player_id, true)
+    }
+
+    fn serialize_player_view(&self, player_id: &PlayerId, include_board: bool) -> WampKwArgs {
+        let mut result = self.serialize_view(false, include_board);
+        result.insert(
+            "private".into(),
+            WampPayloadValue::Object(self.serialize_private(player_id)),
+        );
+        result
+    }
+
+    // The parts of the state only the given player gets to see
+    fn serialize_private(&self, player_id: &PlayerId) -> WampKwArgs {
+        let mut result = wamp_dict! {
+            "player_id" => player_id.to_string(),
+        };
+
+        if self.players.contains_key(player_id) {
+            result.insert(
+                "status".into(),
+                WampPayloadValue::String(JoinStatus::Joined.to_string()),
+            );
+            result.insert(
+                "locked_out".into(),
+                WampPayloadValue::Bool(self.locked_out.contains(player_id)),
+            );
+            // Only those in the game get the code, since it's what lets them review it later
+            result.insert(
+                "invite_code".into(),
+                WampPayloadValue::String(self.invite_code.to_string()),
+            );
+
+            if let Some(submission) = self.get_final_jeopardy_submission(player_id) {
+                result.insert(
+                    "final_jeopardy_submission".into(),
+                    WampPayloadValue::Object(submission),
+                );
+            }
+
+            if let GameState::DailyDouble { controller, .. } = &self.state {
+                if let (true, Some((min_wager, max_wager))) =
+                    (controller == player_id, self.get_daily_double_wager_range())
+                {
+                    result.insert(
+                        "wager_range".into(),
+                        WampPayloadValue::Object(wamp_dict! {
+                            "min" => min_wager.to_string(),
+                            "max" => max_wager.to_string(),
+                        }),
+                    );
+                }
+            }
+        } else if let Some(waiting) = self
+            .waiting_room
+            .iter()
+            .find(|waiting| waiting.id == *player_id)
+        {
+            let status = if waiting.approved {
+                JoinStatus::Waiting
+            } else {
+                JoinStatus::PendingApproval
+            };
+            result.insert(
+                "status".into(),
+                WampPayloadValue::String(status.to_string()),
+            );
+        }
+
+        result
+    }
+
+    /// Works out the update for every channel: the moderator's, the shared
+    /// player channel, and each player's own (including anyone in the waiting room). See
+    /// `DeltaTracker` for what an update looks like.
+    pub(crate) fn make_updates(&mut self) -> Vec<(String, WampKwArgs)> {
+        let board_key = self.state.get_board().map(JeopardyBoard::get_key);
+
+        // (channel, player whose view it is, or None for the moderator and shared views)
+        let mut views = vec![
+            (self.moderator_state_channel.clone(), None),
+            (self.player_state_channel.clone(), None),
+        ];
+        views.extend(
+            self.players
+                .iter()
+                .chain(
+                    self.waiting_room
+                        .iter()
+                        .map(|waiting| (&waiting.id, &waiting.player)),
+                )
+                .map(|(player_id, player)| (player.channel.clone(), Some(player_id.clone()))),
+        );
+
+        let mut updates = Vec::with_capacity(views.len());
+        for (channel, player_id) in views {
+            let include_board = self.delta.needs_board(&channel, board_key);
+            let state = match player_id {
+                Some(ref player_id) => self.serialize_player_view(player_id, include_board),
+                None => self.serialize_view(channel == self.moderator_state_channel, include_board),
+            };
+            let update = self
+                .delta
+                .make_update(&channel, state, board_key, self.version);
+            updates.push((channel, update));
+        }
+
+        self.delta.retain(
+            &updates
+                .iter()
+                .map(|(channel, _)| channel.as_str())
+                .collect(),
+        );
+        updates
+    }
+
+    pub(crate) fn get_final_jeopardy_submission(&self, player_id: &PlayerId) -> Option<WampKwArgs> {
+        match self.state {
+            GameState::FinalJeopardy { .. } => self
+                .players
+                .get(player_id)
+                .map(|player| player.final_jeopardy_info.serialize_submission()),
+            _ => None,
+        }
+    }
+
+    // Who has handed in a Final Jeopardy wager and answer, so the moderator knows when to lock
+    // answers
+    fn serialize_submission_checklist(&self, state: &mut WampKwArgs) {
+        state.insert(
+            "submissions".into(),
+            WampPayloadValue::Object(
+                self.players
+                    .iter()
+                    .map(|(player_id, player)| {
+                        let info = &player.final_jeopardy_info;
+                        let mut result = WampKwArgs::new();
+                        result.insert("eligible".into(), WampPayloadValue::Bool(info.eligible));
+                        result.insert(
+                            "wager_submitted".into(),
+                            WampPayloadValue::Bool(info.wager.is_some()),
+                        );
+                        result.insert(
+                            "answer_submitted".into(),
+                            WampPayloadValue::Bool(info.answer.is_some()),
+                        );
+                        (player_id.to_string(), WampPayloadValue::Object(result))
+                    })
+                    .collect(),
+            ),
+        );
+        state.insert(
+            "all_submitted".into(),
+            WampPayloadValue::Bool(
+                self.players
+                    .values()
+                    .all(|player| player.final_jeopardy_info.is_complete()),
+            ),
+        );
+    }
+
+    // Final standings with everyone's stats, highest score first
+    fn make_summary(&self) -> Vec<WampPayloadValue> {
+        let mut players: Vec<_> = self.players.iter().collect();
+        players.sort_by(|(_, a), (_, b)| {
+            b.score
+                .cmp(&a.score)
+                .then(b.stats.get_correct().cmp(&a.stats.get_correct()))
+        });
+
+        players
+            .into_iter()
+            .map(|(player_id, player)| {
+                let mut result = wamp_dict! {
+                    "id" => player_id.to_string(),
+                    "name" => player.name.clone(),
+                    "score" => player.score.to_string(),
+                };
+                result.insert(
+                    "stats".into(),
+                    WampPayloadValue::Object(player.stats.serialize()),
+                );
+                WampPayloadValue::Object(result)
+            })
+            .collect()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state: self.state.clone(),
+            next_board_id: self.next_board_id,
+            players: self.players.clone(),
+            locked_out: self.locked_out.clone(),
+            buzzed: self.buzzed.clone(),
+        }
+    }
+
+    // Puts a snapshot back, returning a snapshot of what it replaced. Every snapshot has the same
+    // players as the game: seating someone adds them to the snapshots, and removing someone throws
+    // the snapshots away.
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        let current = self.snapshot();
+
+        self.state = snapshot.state;
+        self.next_board_id = snapshot.next_board_id;
+        self.players = snapshot.players;
+        self.locked_out = snapshot.locked_out;
+        self.buzzed = snapshot.buzzed;
+
+        current
+    }
+
+    /// Fails if the game has changed since the version the caller was looking at (if they said).
+    /// This stops a double click from, say, judging the next player's answer too.
+    pub(crate) fn check_version(&self, expected_version: Option<u64>) -> Result<(), Error> {
+        match expected_version {
+            Some(version) if version != self.version => Err(Error::VersionConflict),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a moderator action and remembers the state from before it so that it can be undone.
+    /// If the action fails, everything it changed is rolled back.
+    pub(crate) fn with_history<F>(
+        &mut self,
+        expected_version: Option<u64>,
+        action: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Game) -> Result<(), Error>,
+    {
+        self.check_version(expected_version)?;
+
+        let snapshot = self.snapshot();
+        let event_count = self.event_log.len();
+        let (version, last_activity) = (self.version, self.last_activity);
+
+        match action(self) {
+            Ok(()) => {
+                if self.undo_history.len() >= MAX_UNDO_HISTORY {
+                    self.undo_history.pop_front();
+                }
+                self.undo_history.push_back(snapshot);
+                self.redo_history.clear();
+                Ok(())
+            }
+            Err(err) => {
+                self.restore(snapshot);
+                self.event_log.truncate(event_count);
+                self.version = version;
+                self.last_activity = last_activity;
+                Err(err)
+            }
+        }
+    }
+
+    /// Rolls back the last moderator action. Anything players did since then (like buzzing in or
+    /// wagering) is rolled back with it.
+    pub(crate) fn undo(&mut self) -> Result<(), Error> {
+        let snapshot = self.undo_history.pop_back().ok_or(Error::NoHistory)?;
+        let current = self.restore(snapshot);
+        self.redo_history.push(current);
+        let (board_id, squares) = self.get_board_status();
+        self.log_event(GameEvent::Undo {
+            scores: self.get_scores(),
+            board_id,
+            squares,
+        });
+        Ok(())
+    }
+
+    /// Re-applies the last moderator action that was undone.
+    pub(crate) fn redo(&mut self) -> Result<(), Error> {
+        let snapshot = self.redo_history.pop().ok_or(Error::NoHistory)?;
+        let current = self.restore(snapshot);
+        self.undo_history.push_back(current);
+        let (board_id, squares) = self.get_board_status();
+        self.log_event(GameEvent::Redo {
+            scores: self.get_scores(),
+            board_id,
+            squares,
+        });
+        Ok(())
+    }
+
+    // The legal wagers for the Daily Double being wagered on, if there is one: at least the minimum,
+    // and at most the player's score or the top clue value of this round's board, whichever is
+    // larger. It stays available once the wager is in, so the moderator can see it until they
+    // reveal the clue.
+    fn get_daily_double_wager_range(&self) -> Option<(i64, i64)> {
+        match &self.state {
+            GameState::DailyDouble {
+                board,
+                controller,
+                stage: DailyDoubleStage::Revealed | DailyDoubleStage::WagerSubmitted { .. },
+                ..
+            } => {
+                let score = self
+                    .players
+                    .get(controller)
+                    .map_or(0, |player| player.score);
+                Some((
+                    MIN_DAILY_DOUBLE_WAGER,
+                    board.get_top_value().max(score).max(MIN_DAILY_DOUBLE_WAGER),
+                ))
+            }
+
+            _ => None,
+        }
+    }
+
+    // The ID of the board in play (if any) and the squares played on it, so that a review can show
+    // where an undo or redo left things.
+    fn get_board_status(&self) -> (Option<usize>, Vec<SquareStatus>) {
+        match self.state.get_board() {
+            Some(board) => (Some(board.get_id()), board.get_square_statuses()),
+            None => (None, Vec::new()),
+        }
+    }
+
+    fn get_scores(&self) -> HashMap<PlayerId, i64> {
+        self.players
+            .iter()
+            .map(|(player_id, player)| (player_id.clone(), player.score))
+            .collect()
+    }
+
+    fn is_full(&self) -> bool {
+        self.max_players
+            .map(|max_players| self.players.len() >= max_players)
+            .unwrap_or(false)
+    }
+
+    /// Checks whether anyone in the game (including the moderator and the waiting room) already
+    /// goes by the given name.
+    fn is_name_taken(&self, name: &str) -> bool {
+        let key = names::name_key(name);
+        std::iter::once(&self.moderator)
+            .chain(self.players.values())
+            .chain(self.waiting_room.iter().map(|waiting| &waiting.player))
+            .any(|player| names::name_key(&player.name) == key)
+    }
+
+    /// Handles a request to join the game. The player is seated right away if there's room and
+    /// no approval is needed; otherwise they're put in the waiting room, unless that's full too.
+    pub(crate) fn add_player(&mut self, player: Player) -> Result<(PlayerId, JoinStatus), Error> {
+        if self.is_name_taken(&player.name) {
+            return Err(Error::NameTaken);
+        }
+
+        let id = PlayerId(Uuid::new_v4());
 
-        result.insert("is_moderator".into(), WampPayloadValue::Bool(for_moderator));
-        result.insert(
-            "moderator".into(),
-            WampPayloadValue::String(self.moderator.name.clone()),
-        );
-        result.insert(
-            "min_year".into(),
-            WampPayloadValue::Number(JEOPARDY_DATA.get().unwrap().min_year.into()),
-        );
-        result.insert(
-            "max_year".into(),
-            WampPayloadValue::Number(JEOPARDY_DATA.get().unwrap().max_year.into()),
-        );
+        let status = if self.approve_joins {
+            JoinStatus::PendingApproval
+        } else if self.is_full() {
+            JoinStatus::Waiting
+        } else {
+            self.seat_player(id.clone(), player);
+            return Ok((id, JoinStatus::Joined));
+        };
+        if self.waiting_room.len() >= MAX_WAITING_PLAYERS {
+            return Err(Error::WaitingRoomFull);
+        }
 
-        result
+        info!("Adding player to waiting room: {:?} => {}", id, player.name);
+        self.waiting_room.push_back(WaitingPlayer {
+            id: id.clone(),
+            player,
+            approved: !self.approve_joins,
+        });
+        self.mark_changed();
+
+        Ok((id, status))
     }
 
-    pub(crate) fn add_player(&mut self, player: Player) -> PlayerId {
-        let id = PlayerId(Uuid::new_v4());
+    /// Sets the player cap (None for no cap) and whether joins need approval. Anyone approved in
+    /// the waiting room is seated if the cap now allows it.
+    pub(crate) fn set_join_rules(&mut self, max_players: Option<usize>, approve_joins: bool) {
+        self.max_players = max_players;
+        self.approve_joins = approve_joins;
+        self.mark_changed();
+        self.fill_seats();
+    }
+
+    /// Accepts or rejects someone in the waiting room who needs approval.
+    pub(crate) fn approve_join(&mut self, id: &PlayerId, approve: bool) -> Result<(), Error> {
+        let index = self
+            .waiting_room
+            .iter()
+            .position(|waiting| waiting.id == *id && !waiting.approved)
+            .ok_or(Error::NoSuchPlayer)?;
+
+        if approve {
+            self.waiting_room[index].approved = true;
+            self.fill_seats();
+        } else {
+            self.waiting_room.remove(index);
+            self.mark_changed();
+        }
+
+        Ok(())
+    }
+
+    // Seats approved players from the waiting room, in order, until the game is full.
+    fn fill_seats(&mut self) {
+        while !self.is_full() {
+            let index = match self
+                .waiting_room
+                .iter()
+                .position(|waiting| waiting.approved)
+            {
+                Some(index) => index,
+                None => break,
+            };
+
+            let waiting = self.waiting_room.remove(index).unwrap();
+            self.seat_player(waiting.id, waiting.player);
+        }
+    }
+
+    fn seat_player(&mut self, id: PlayerId, player: Player) {
         info!("Adding player: {:?} => {}", id, player.name);
+        self.log_event(GameEvent::PlayerJoined {
+            player: id.clone(),
+            name: player.name.clone(),
+        });
+        // Undoing something from before they joined shouldn't take their seat away
+        for snapshot in self
+            .undo_history
+            .iter_mut()
+            .chain(self.redo_history.iter_mut())
+        {
+            snapshot.players.insert(id.clone(), player.clone());
+        }
         self.players.insert(id.clone(), player);
 
         // If we're currently in a WaitingForSquareSelection state and there's no controller,
@@ -462,20 +1635,42 @@ impl Game {
             ..
         } = self.state
         {
-            *controller = Some(id.clone());
+            *controller = Some(id);
         }
-
-        id
     }
 
+    /// Removes a player from the game (or from the waiting room), seating the next person in line
+    /// if there's room for them now.
     pub(crate) fn remove_player(&mut self, player_id: PlayerId) -> bool {
+        if let Some(index) = self
+            .waiting_room
+            .iter()
+            .position(|waiting| waiting.id == player_id)
+        {
+            self.waiting_room.remove(index);
+            self.mark_changed();
+            return true;
+        }
+
         if !self.players.contains_key(&player_id) {
             return false;
         }
 
-        self.players.remove(&player_id);
+        // Old snapshots might refer to the player (as the controller, for example), so they can't
+        // be restored any more.
+        self.undo_history.clear();
+        self.redo_history.clear();
+
+        if let Some(player) = self.players.remove(&player_id) {
+            self.log_event(GameEvent::PlayerLeft {
+                player: player_id.clone(),
+                name: player.name,
+            });
+        }
         // Will be none if there are no longer any players
         let new_player = self.players.keys().next();
+        // The square in play, if leaving means nobody can finish it
+        let mut finished_square = None;
 
         match (&mut self.state, new_player) {
             (GameState::NoBoard, _) => {}
@@ -582,6 +1777,7 @@ impl Game {
                     new_board
                         .get_square_mut(location)
                         .set_flip_state(SquareState::Finished);
+                    finished_square = Some(*location);
 
                     self.state = GameState::WaitingForSquareSelection {
                         controller: None,
@@ -617,38 +1813,44 @@ impl Game {
                     Some(controller.clone())
                 };
 
-                if *active_player == player_id {
-                    new_board
-                        .get_square_mut(location)
-                        .set_flip_state(SquareState::Finished);
-
-                    self.state = GameState::WaitingForSquareSelection {
-                        controller: new_controller,
-                        board: new_board,
-                    };
-                } else {
+                match new_controller {
                     // If there's now no players left, it must be the case that
                     // the active player, controller, and removal target are all
-                    // the same player. Therefore, if we get here, there must have
-                    // been multiple players prior to the remove call, and there
-                    // will be a new controller.
-                    self.state = GameState::WaitingForAnswer {
-                        board: new_board,
-                        location: *location,
-                        controller: new_controller.expect("no new controller"),
-                        active_player: active_player.clone(),
-                        value: *value,
-                    };
+                    // the same player. So if the active player is still here,
+                    // there's a new controller; if somehow there isn't, the
+                    // square is finished below rather than left without one.
+                    Some(new_controller) if *active_player != player_id => {
+                        self.state = GameState::WaitingForAnswer {
+                            board: new_board,
+                            location: *location,
+                            controller: new_controller,
+                            active_player: active_player.clone(),
+                            value: *value,
+                        };
+                    }
+
+                    new_controller => {
+                        new_board
+                            .get_square_mut(location)
+                            .set_flip_state(SquareState::Finished);
+                        finished_square = Some(*location);
+
+                        self.state = GameState::WaitingForSquareSelection {
+                            controller: new_controller,
+                            board: new_board,
+                        };
+                    }
                 }
             }
 
-            // If the player making a daily double wager leaves, always finish the square
+            // If the player playing a daily double leaves, always finish the square
             // then just go back to WaitingForSquareSelection.
             (
-                GameState::WaitingForDailyDoubleWager {
+                GameState::DailyDouble {
                     ref mut board,
                     location,
                     controller,
+                    stage,
                 },
                 new_player,
             ) => {
@@ -659,16 +1861,18 @@ impl Game {
                     new_board
                         .get_square_mut(location)
                         .set_flip_state(SquareState::Finished);
+                    finished_square = Some(*location);
 
                     self.state = GameState::WaitingForSquareSelection {
                         board: new_board,
                         controller: new_player.cloned(),
                     };
                 } else {
-                    self.state = GameState::WaitingForDailyDoubleWager {
+                    self.state = GameState::DailyDouble {
                         board: new_board,
                         location: *location,
                         controller: controller.clone(),
+                        stage: *stage,
                     };
                 }
             }
@@ -677,9 +1881,57 @@ impl Game {
             (GameState::FinalJeopardy { .. }, _) => {}
         };
 
+        if let Some(location) = finished_square {
+            self.log_event(GameEvent::SquareStateChanged {
+                location,
+                state: SquareState::Finished,
+            });
+        }
+
+        self.fill_seats();
         true
     }
 
+    /// Removes a player (seated or waiting) and, if `ban` is set, stops anyone with the same
+    /// avatar from joining again.
+    pub(crate) fn kick_player(&mut self, player_id: PlayerId, ban: bool) -> Result<(), Error> {
+        let player = match self.players.get(&player_id) {
+            Some(player) => player,
+            None => {
+                &self
+                    .waiting_room
+                    .iter()
+                    .find(|waiting| waiting.id == player_id)
+                    .ok_or(Error::NoSuchPlayer)?
+                    .player
+            }
+        };
+        let avatar_url = player.avatar_url.clone();
+        let profile = player.profile.clone();
+
+        info!("Kicking player {:?} (ban: {})", player_id, ban);
+        self.log_event(GameEvent::PlayerKicked {
+            player: player_id.clone(),
+            banned: ban,
+        });
+        self.remove_player(player_id);
+        if ban {
+            self.banned_avatars.insert(avatar_url);
+            self.banned_profiles.extend(profile);
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether someone asking to join was banned, either by their avatar or their profile.
+    pub(crate) fn is_banned(&self, player: &Player) -> bool {
+        self.banned_avatars.contains(&player.avatar_url)
+            || player
+                .profile
+                .as_ref()
+                .is_some_and(|profile| self.banned_profiles.contains(profile))
+    }
+
     pub(crate) fn load_new_board(
         &mut self,
         multiplier: i64,
@@ -689,20 +1941,26 @@ impl Game {
         max_year: u16,
         seed: Seed,
     ) -> Result<(), Error> {
-        self.next_board_id += 1;
-        let board = self
-            .make_random_board(
-                multiplier,
-                daily_double_count,
-                categories,
-                min_year,
-                max_year,
-                self.next_board_id,
-                seed,
-            )
-            .ok_or(Error::TooManyDailyDoubles)?;
+        let board_id = self.next_board_id + 1;
+        let board = self.make_random_board(
+            multiplier,
+            daily_double_count,
+            categories,
+            min_year,
+            max_year,
+            board_id,
+            seed,
+        )?;
+        self.next_board_id = board_id;
         let new_controller = self.get_random_player_with_lowest_score();
 
+        self.log_event(GameEvent::BoardLoaded {
+            board_id: board.get_id(),
+            seed: board.get_seed().to_string(),
+            value_multiplier: board.value_multiplier,
+            categories: board.get_category_titles(),
+        });
+
         match self.state {
             GameState::NoBoard => {
                 self.state = GameState::WaitingForSquareSelection {
@@ -719,7 +1977,7 @@ impl Game {
             }
 
             GameState::WaitingForAnswer { .. }
-            | GameState::WaitingForDailyDoubleWager { .. }
+            | GameState::DailyDouble { .. }
             | GameState::WaitingForEnableBuzzer { .. }
             | GameState::WaitingForBuzzer { .. }
             | GameState::FinalJeopardy { .. } => {
@@ -739,7 +1997,41 @@ impl Game {
         min_year: u16,
         max_year: u16,
     ) -> Result<(), Error> {
-        let question = self.get_random_final_jeopardy(&mut seed.to_rng(), min_year, max_year);
+        let question = self.get_random_final_jeopardy(&mut seed.to_rng(), min_year, max_year)?;
+        self.log_event(GameEvent::FinalJeopardyStarted {
+            category: question.category.clone(),
+            clue: question.clue.clone(),
+            answer: question.answer.clone(),
+        });
+
+        // Only players in the black can play, and their responses are revealed from the lowest
+        // score going in up to the highest.
+        let mut reveal_order: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.score > 0)
+            .collect();
+        reveal_order.sort_by(|(_, a), (_, b)| a.score.cmp(&b.score).then(a.name.cmp(&b.name)));
+        let reveal_order: Vec<_> = reveal_order
+            .into_iter()
+            .map(|(player_id, _)| player_id.clone())
+            .collect();
+
+        let mut ineligible = Vec::new();
+        for (player_id, player) in self.players.iter_mut() {
+            player.final_jeopardy_info = FinalJeopardyInfo {
+                eligible: player.score > 0,
+                ..Default::default()
+            };
+            if !player.final_jeopardy_info.eligible {
+                ineligible.push(player_id.clone());
+            }
+        }
+        self.log_event(GameEvent::FinalJeopardyEligibility {
+            eligible: reveal_order.clone(),
+            ineligible,
+        });
+
         self.state = GameState::FinalJeopardy {
             category_name: question.category.clone(),
             air_year: question.air_year,
@@ -747,10 +2039,9 @@ impl Game {
             question_revealed: false,
             answers_locked: false,
             answer: question.answer.clone(),
+            reveal_order,
+            next_reveal: 0,
         };
-        self.players.iter_mut().for_each(|(_, player)| {
-            player.final_jeopardy_info = Default::default();
-        });
 
         Ok(())
     }
@@ -782,34 +2073,40 @@ impl Game {
         max_year: u16,
         id: usize,
         seed: Seed,
-    ) -> Option<Box<JeopardyBoard>> {
+    ) -> Result<Box<JeopardyBoard>, Error> {
         let mut rng = seed.to_rng();
 
         let categories = (0..category_count)
             .map(|_| self.get_random_category(&mut rng, min_year, max_year))
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         let mut board = Box::new(JeopardyBoard::new(categories, multiplier, id, seed));
 
         let daily_doubles =
-            Location::gen_random_locations(&mut rng, daily_double_count, category_count)?;
+            Location::gen_random_locations(&mut rng, daily_double_count, category_count)
+                .ok_or(Error::TooManyDailyDoubles)?;
         for location in daily_doubles {
             board.get_square_mut(&location).is_daily_double = true;
         }
 
-        Some(board)
+        Ok(board)
     }
 
-    fn get_random_category<R: Rng>(&self, rng: &mut R, min_year: u16, max_year: u16) -> Category {
+    // Both of these fail with `Error::BadArgument` if nothing aired in the given years.
+    fn get_random_category<R: Rng>(
+        &self,
+        rng: &mut R,
+        min_year: u16,
+        max_year: u16,
+    ) -> Result<Category, Error> {
         JEOPARDY_DATA
-            .get()
-            .unwrap()
+            .read()
             .categories
             .iter()
             .filter(|cat| cat.air_year >= min_year && cat.air_year <= max_year)
             .choose(rng)
-            .unwrap()
-            .clone()
+            .cloned()
+            .ok_or(Error::BadArgument)
     }
 
     fn get_random_final_jeopardy<R: Rng>(
@@ -817,34 +2114,44 @@ impl Game {
         rng: &mut R,
         min_year: u16,
         max_year: u16,
-    ) -> FinalJeopardyQuestion {
+    ) -> Result<FinalJeopardyQuestion, Error> {
         JEOPARDY_DATA
-            .get()
-            .unwrap()
+            .read()
             .final_jeopardy_questions
             .iter()
             .filter(|cat| cat.air_year >= min_year && cat.air_year <= max_year)
             .choose(rng)
-            .unwrap()
-            .clone()
+            .cloned()
+            .ok_or(Error::BadArgument)
     }
 
     pub(crate) fn select_square(&mut self, location: &Location) -> Result<(), Error> {
-        let new_state = match &mut self.state {
+        let (new_state, event) = match &mut self.state {
             GameState::WaitingForSquareSelection {
                 ref mut board,
                 controller: Some(controller),
             } => {
                 board.get_square_mut(location).flip()?;
 
+                let square = board.get_square(location);
+                let event = GameEvent::SquareSelected {
+                    location: *location,
+                    category: board.get_category_title(location).to_string(),
+                    value: board.get_square_value(location),
+                    is_daily_double: square.is_daily_double,
+                    clue: square.clue.clone(),
+                    answer: square.answer.clone(),
+                };
+
                 // Move to new state
                 let mut new_board = Box::new(DUMMY_BOARD);
                 std::mem::swap(&mut new_board, board);
-                if new_board.get_square(location).is_daily_double {
-                    GameState::WaitingForDailyDoubleWager {
+                let new_state = if new_board.get_square(location).is_daily_double {
+                    GameState::DailyDouble {
                         board: new_board,
                         location: *location,
                         controller: controller.clone(),
+                        stage: DailyDoubleStage::Revealed,
                     }
                 } else {
                     GameState::WaitingForEnableBuzzer {
@@ -852,12 +2159,17 @@ impl Game {
                         location: *location,
                         controller: controller.clone(),
                     }
-                }
+                };
+
+                (new_state, event)
             }
 
             _ => return Err(Error::InvalidStateForOperation),
         };
         self.state = new_state;
+        self.locked_out.clear();
+        self.buzzed.clear();
+        self.log_event(event);
         Ok(())
     }
 
@@ -881,53 +2193,50 @@ impl Game {
             _ => return Err(Error::InvalidStateForOperation),
         };
 
+        self.log_event(GameEvent::BuzzerEnabled);
         Ok(())
     }
 
     pub(crate) fn submit_wager(&mut self, caller_id: &PlayerId, wager: i64) -> Result<(), Error> {
+        let daily_double_range = self.get_daily_double_wager_range();
+
         match &mut self.state {
-            GameState::WaitingForDailyDoubleWager {
-                ref mut board,
+            GameState::DailyDouble {
                 controller,
-                location,
+                stage: ref mut stage @ DailyDoubleStage::Revealed,
+                ..
             } if *controller == *caller_id => {
-                // Move to new state
-                if wager < MIN_DAILY_DOUBLE_WAGER {
-                    return Err(Error::DailyDoubleWagerOutOfRange);
+                if !self.players.contains_key(controller) {
+                    return Err(Error::NoSuchPlayer);
                 }
-
-                let cur_score = self
-                    .players
-                    .get(controller)
-                    .ok_or(Error::NoSuchPlayer)?
-                    .score;
-                let max_default_bid = MIN_MAX_DAILY_DOUBLE_WAGER_FACTOR * board.value_multiplier;
-                let max_bid = max_default_bid.max(cur_score);
-                if wager > max_bid {
+                if !daily_double_range.is_some_and(|(min, max)| (min..=max).contains(&wager)) {
                     return Err(Error::DailyDoubleWagerOutOfRange);
                 }
 
-                let mut new_board = Box::new(DUMMY_BOARD);
-                std::mem::swap(&mut new_board, board);
-                self.state = GameState::WaitingForAnswer {
-                    board: new_board,
-                    location: *location,
-                    active_player: controller.clone(),
-                    controller: controller.clone(),
-                    value: wager,
-                };
+                // The wager is locked in; the clue stays hidden until the moderator reveals it
+                *stage = DailyDoubleStage::WagerSubmitted { wager };
+                self.log_event(GameEvent::DailyDoubleWager {
+                    player: caller_id.clone(),
+                    wager,
+                });
             }
 
             GameState::FinalJeopardy {
                 question_revealed: false,
                 ..
             } => {
-                if let Some(ref mut player) = self.players.get_mut(caller_id) {
-                    if wager < 0 || wager > player.score {
-                        return Err(Error::FinalJeopardyWagerOutOfRange);
-                    }
-                    player.final_jeopardy_info.wager = Some(wager);
+                let player = self.players.get_mut(caller_id).ok_or(Error::NoSuchPlayer)?;
+                if !player.final_jeopardy_info.eligible {
+                    return Err(Error::NotEligible);
                 }
+                if wager < 0 || wager > player.score {
+                    return Err(Error::FinalJeopardyWagerOutOfRange);
+                }
+                player.final_jeopardy_info.wager = Some(wager);
+                self.log_event(GameEvent::FinalJeopardyWager {
+                    player: caller_id.clone(),
+                    wager,
+                });
             }
 
             _ => return Err(Error::InvalidStateForOperation),
@@ -936,6 +2245,31 @@ impl Game {
         Ok(())
     }
 
+    pub(crate) fn reveal_daily_double_clue(&mut self) -> Result<(), Error> {
+        match &mut self.state {
+            GameState::DailyDouble {
+                ref mut board,
+                location,
+                stage,
+                ..
+            } => {
+                let wager = match stage {
+                    DailyDoubleStage::WagerSubmitted { wager } => *wager,
+                    _ => return Err(Error::InvalidStateForOperation),
+                };
+
+                board.get_square_mut(location).show_daily_double_clue()?;
+                *stage = DailyDoubleStage::ClueShown { wager };
+
+                let location = *location;
+                self.log_event(GameEvent::DailyDoubleClueRevealed { location });
+                Ok(())
+            }
+
+            _ => Err(Error::InvalidStateForOperation),
+        }
+    }
+
     pub(crate) fn submit_final_jeopardy_answer(
         &mut self,
         id: &PlayerId,
@@ -946,11 +2280,15 @@ impl Game {
                 answers_locked: false,
                 ..
             } => {
-                if let Some(player) = self.players.get_mut(id) {
-                    player.final_jeopardy_info.answer = Some(answer.to_string());
-                } else {
-                    warn!("Tried to submit final jeopardy answer for non-existant player");
+                let player = self.players.get_mut(id).ok_or(Error::NoSuchPlayer)?;
+                if !player.final_jeopardy_info.eligible {
+                    return Err(Error::NotEligible);
                 }
+                player.final_jeopardy_info.answer = Some(answer.to_string());
+                self.log_event(GameEvent::FinalJeopardyAnswer {
+                    player: id.clone(),
+                    answer: answer.to_string(),
+                });
 
                 Ok(())
             }
@@ -966,6 +2304,7 @@ impl Game {
                 ..
             } => {
                 *question_revealed = true;
+                self.log_event(GameEvent::FinalJeopardyQuestionRevealed);
                 Ok(())
             }
 
@@ -980,6 +2319,7 @@ impl Game {
                 ..
             } => {
                 *answers_locked = true;
+                self.log_event(GameEvent::FinalJeopardyAnswersLocked);
                 Ok(())
             }
 
@@ -1003,6 +2343,10 @@ impl Game {
                             player.final_jeopardy_info.wager_revealed = true;
                         }
                     }
+                    self.log_event(GameEvent::FinalJeopardyInfoRevealed {
+                        player: player_id.clone(),
+                        info_type,
+                    });
                 } else {
                     warn!("Tried to reveal final jeopardy info for player not in map");
                 }
@@ -1014,25 +2358,84 @@ impl Game {
         }
     }
 
+    /// Reveals the next step of the Final Jeopardy sequence: each eligible player's answer, then
+    /// (once it's been judged) their wager, starting from the lowest score going in.
+    pub(crate) fn reveal_next_final_jeopardy_info(&mut self) -> Result<(), Error> {
+        let (player_id, info_type) = match &mut self.state {
+            GameState::FinalJeopardy {
+                answers_locked: true,
+                reveal_order,
+                next_reveal,
+                ..
+            } => loop {
+                let player_id = reveal_order
+                    .get(*next_reveal)
+                    .ok_or(Error::InvalidStateForOperation)?;
+
+                // Skip anyone who left the game after Final Jeopardy started
+                let info = match self.players.get(player_id) {
+                    Some(player) => &player.final_jeopardy_info,
+                    None => {
+                        *next_reveal += 1;
+                        continue;
+                    }
+                };
+
+                if !info.answer_revealed {
+                    break (player_id.clone(), FinalJeopardyInfoType::Answer);
+                } else if info.judgment.is_none() {
+                    // The moderator has to judge the answer before the wager is shown
+                    return Err(Error::InvalidStateForOperation);
+                } else {
+                    let player_id = player_id.clone();
+                    *next_reveal += 1;
+                    break (player_id, FinalJeopardyInfoType::Wager);
+                }
+            },
+
+            _ => return Err(Error::InvalidStateForOperation),
+        };
+
+        self.reveal_final_jeopardy_info(&player_id, info_type)
+    }
+
+    /// Judges a player's Final Jeopardy answer. Answers can only be judged once they're locked in
+    /// and the player's answer has been revealed, and only once (mistakes are fixed with undo).
     pub(crate) fn evaluate_final_jeopardy_answer(
         &mut self,
         player_id: &PlayerId,
         answer_type: AnswerType,
     ) -> Result<(), Error> {
         match &self.state {
-            GameState::FinalJeopardy { .. } => {
+            GameState::FinalJeopardy {
+                answers_locked,
+                reveal_order,
+                ..
+            } => {
                 if let Some(player) = self.players.get_mut(player_id) {
-                    let wager = player.final_jeopardy_info.wager.unwrap_or(0);
-
-                    match answer_type {
-                        AnswerType::Correct => {
-                            player.score += wager;
-                        }
-                        AnswerType::Incorrect => {
-                            player.score -= wager;
-                        }
-                        AnswerType::Skip => {}
+                    let info = &mut player.final_jeopardy_info;
+                    if !info.eligible {
+                        return Err(Error::NotEligible);
                     }
+                    if !*answers_locked
+                        || !reveal_order.contains(player_id)
+                        || !info.answer_revealed
+                        || info.judgment.is_some()
+                    {
+                        return Err(Error::NotAllowed);
+                    }
+
+                    let wager = info.wager.unwrap_or(0);
+                    player.score += info.score_change(Some(answer_type));
+                    info.judgment = Some(answer_type);
+
+                    let score = player.score;
+                    self.log_event(GameEvent::FinalJeopardyJudged {
+                        player: player_id.clone(),
+                        judgment: answer_type,
+                        wager,
+                        score,
+                    });
                 } else {
                     warn!("Tried to evalue final jeopardy answer for a non-existant player");
                 }
@@ -1051,9 +2454,13 @@ impl Game {
                 location,
                 controller,
             } => {
-                if !self.players.contains_key(&id) {
-                    return Err(Error::NoSuchPlayer);
+                if self.locked_out.contains(&id) {
+                    return Err(Error::LockedOut);
                 }
+                let player = self.players.get_mut(&id).ok_or(Error::NoSuchPlayer)?;
+                player
+                    .stats
+                    .record_buzz(self.buzzed.insert(id.clone()), true);
 
                 let value = board.get_square_value(location);
 
@@ -1062,16 +2469,31 @@ impl Game {
                 GameState::WaitingForAnswer {
                     board: new_board,
                     location: *location,
-                    active_player: id,
+                    active_player: id.clone(),
                     controller: controller.clone(),
                     value,
                 }
             }
 
+            // Buzzing in after someone else won the race still counts as an attempt, as long as
+            // the player could have buzzed in on this clue (and only once per clue). It isn't an
+            // error, since the player didn't do anything wrong; they were just too slow.
+            GameState::WaitingForAnswer { active_player, .. }
+                if *active_player != id && !self.locked_out.contains(&id) =>
+            {
+                let player = self.players.get_mut(&id).ok_or(Error::NoSuchPlayer)?;
+                if self.buzzed.insert(id.clone()) {
+                    player.stats.record_buzz(true, false);
+                    self.log_event(GameEvent::LateBuzz { player: id });
+                }
+                return Ok(());
+            }
+
             _ => return Err(Error::InvalidStateForOperation),
         };
 
         self.state = new_state;
+        self.log_event(GameEvent::Buzz { player: id });
         Ok(())
     }
 
@@ -1088,11 +2510,26 @@ impl Game {
                 },
                 AnswerType::Correct,
             ) => {
+                let square = board.get_square(location);
+                let is_daily_double = square.is_daily_double;
+                let face_value = board.get_square_value(location);
+                let category = board.get_category_title(location).to_string();
+
                 let player = self
                     .players
                     .get_mut(active_player)
                     .ok_or(Error::NoSuchPlayer)?;
                 player.score += *value;
+                player
+                    .stats
+                    .record_answer(&category, true, *value, face_value, is_daily_double);
+
+                let event = GameEvent::AnswerJudged {
+                    player: active_player.clone(),
+                    judgment: AnswerType::Correct,
+                    value: *value,
+                    score: player.score,
+                };
 
                 let mut new_board = Box::new(DUMMY_BOARD);
                 std::mem::swap(&mut new_board, board);
@@ -1102,7 +2539,8 @@ impl Game {
                 self.state = GameState::WaitingForSquareSelection {
                     board: new_board,
                     controller: Some(active_player.clone()),
-                }
+                };
+                self.log_event(event);
             }
 
             // On an incorrect answer, the controller does not change, and the question does not end.
@@ -1116,11 +2554,27 @@ impl Game {
                 },
                 AnswerType::Incorrect,
             ) => {
+                let square = board.get_square(location);
+                let is_daily_double = square.is_daily_double;
+                let face_value = board.get_square_value(location);
+                let category = board.get_category_title(location).to_string();
+
                 let player = self
                     .players
                     .get_mut(active_player)
                     .ok_or(Error::NoSuchPlayer)?;
                 player.score -= *value;
+                player
+                    .stats
+                    .record_answer(&category, false, *value, face_value, is_daily_double);
+
+                let event = GameEvent::AnswerJudged {
+                    player: active_player.clone(),
+                    judgment: AnswerType::Incorrect,
+                    value: *value,
+                    score: player.score,
+                };
+                self.locked_out.insert(active_player.clone());
 
                 let mut new_board = Box::new(DUMMY_BOARD);
                 std::mem::swap(&mut new_board, board);
@@ -1130,6 +2584,62 @@ impl Game {
                     controller: controller.clone(),
                     location: *location,
                 };
+                self.log_event(event);
+            }
+
+            // A Daily Double is only played by the controller, so the clue ends either way.
+            (
+                GameState::DailyDouble {
+                    board,
+                    location,
+                    controller,
+                    stage: DailyDoubleStage::ClueShown { wager },
+                },
+                AnswerType::Correct,
+            )
+            | (
+                GameState::DailyDouble {
+                    board,
+                    location,
+                    controller,
+                    stage: DailyDoubleStage::ClueShown { wager },
+                },
+                AnswerType::Incorrect,
+            ) => {
+                let correct = matches!(answer, AnswerType::Correct);
+                let face_value = board.get_square_value(location);
+                let category = board.get_category_title(location).to_string();
+
+                let player = self
+                    .players
+                    .get_mut(controller)
+                    .ok_or(Error::NoSuchPlayer)?;
+                if correct {
+                    player.score += *wager;
+                } else {
+                    player.score -= *wager;
+                }
+                player
+                    .stats
+                    .record_answer(&category, correct, *wager, face_value, true);
+
+                let event = GameEvent::AnswerJudged {
+                    player: controller.clone(),
+                    judgment: answer,
+                    value: *wager,
+                    score: player.score,
+                };
+
+                let mut new_board = Box::new(DUMMY_BOARD);
+                std::mem::swap(&mut new_board, board);
+
+                new_board.get_square_mut(location).finish()?;
+
+                self.state = GameState::WaitingForSquareSelection {
+                    board: new_board,
+                    controller: Some(controller.clone()),
+                };
+                self.log_event(event);
             }
 
             // On a skip, the question ends, and the controller does not change.
@@ -1151,10 +2661,11 @@ impl Game {
                 AnswerType::Skip,
             )
             | (
-                GameState::WaitingForDailyDoubleWager {
+                GameState::DailyDouble {
                     controller,
                     board,
                     location,
+                    ..
                 },
                 AnswerType::Skip,
             ) => {
@@ -1163,10 +2674,12 @@ impl Game {
 
                 new_board.get_square_mut(location).finish()?;
 
+                let location = *location;
                 self.state = GameState::WaitingForSquareSelection {
                     board: new_board,
                     controller: Some(controller.clone()),
                 };
+                self.log_event(GameEvent::ClueSkipped { location });
             }
 
             (_, _) => return Err(Error::InvalidStateForOperation),
@@ -1182,23 +2695,441 @@ impl Game {
     ) -> Result<(), Error> {
         match self.state {
             GameState::WaitingForSquareSelection { ref mut board, .. }
-            | GameState::WaitingForDailyDoubleWager { ref mut board, .. }
+            | GameState::DailyDouble { ref mut board, .. }
             | GameState::WaitingForBuzzer { ref mut board, .. }
             | GameState::WaitingForAnswer { ref mut board, .. } => {
-                board.get_square_mut(location).set_flip_state(state);
+                board.get_square_mut(location).set_flip_state(state.clone());
             }
 
             _ => return Err(Error::InvalidStateForOperation),
         }
 
+        self.log_event(GameEvent::SquareStateChanged {
+            location: *location,
+            state,
+        });
         Ok(())
     }
 
     pub(crate) fn set_player_score(&mut self, player: &PlayerId, score: i64) -> Result<(), Error> {
-        self.players
-            .get_mut(player)
-            .ok_or(Error::NoSuchPlayer)?
-            .score = score;
+        let old_score = std::mem::replace(
+            &mut self
+                .players
+                .get_mut(player)
+                .ok_or(Error::NoSuchPlayer)?
+                .score,
+            score,
+        );
+        self.log_event(GameEvent::ScoreChanged {
+            player: player.clone(),
+            old_score,
+            new_score: score,
+        });
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod game_tests {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use serde_json::json;
+    use wamp_async::WampPayloadValue;
+
+    use super::{
+        board::{Clue, Location, DUMMY_BOARD},
+        events::{GameEvent, LoggedEvent},
+        AnswerType, FinalJeopardyInfo, FinalJeopardyInfoType, Game, GameState, JoinStatus, Player,
+    };
+    use crate::{errors::Error, seed::Seed, GameId, InviteCode, PlayerId};
+
+    fn new_game() -> Game {
+        Game::new(
+            GameId(Uuid::new_v4()),
+            Player::new("Moderator".into(), "/avatars/moderator.png".into()),
+            InviteCode(Seed::new_random()),
+        )
+    }
+
+    fn join(game: &mut Game, name: &str) -> PlayerId {
+        let (id, status) = game
+            .add_player(Player::new(name.into(), format!("/avatars/{}.png", name)))
+            .unwrap();
+        assert!(matches!(status, JoinStatus::Joined));
+        id
+    }
+
+    fn score(game: &Game, id: &PlayerId) -> i64 {
+        game.players[id].score
+    }
+
+    #[test]
+    fn undoes_and_redoes_scoring() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+
+        game.with_history(None, |game| game.set_player_score(&alice, 400))
+            .unwrap();
+        game.with_history(None, |game| game.set_player_score(&alice, 1000))
+            .unwrap();
+
+        game.undo().unwrap();
+        assert_eq!(score(&game, &alice), 400);
+        game.undo().unwrap();
+        assert_eq!(score(&game, &alice), 0);
+        assert!(matches!(game.undo(), Err(Error::NoHistory)));
+
+        game.redo().unwrap();
+        game.redo().unwrap();
+        assert_eq!(score(&game, &alice), 1000);
+        assert!(matches!(game.redo(), Err(Error::NoHistory)));
+
+        // A new action starts a new branch of history
+        game.undo().unwrap();
+        game.with_history(None, |game| game.set_player_score(&alice, -200))
+            .unwrap();
+        assert!(matches!(game.redo(), Err(Error::NoHistory)));
+        assert_eq!(score(&game, &alice), -200);
+
+        // Failed actions leave nothing behind
+        let bob = PlayerId(Uuid::new_v4());
+        let version = game.version;
+        assert!(game
+            .with_history(None, |game| game.set_player_score(&bob, 100))
+            .is_err());
+        assert_eq!(game.version, version);
+        game.undo().unwrap();
+        assert_eq!(score(&game, &alice), 400);
+
+        // Undoing a new board takes its number back too
+        let load_board = |game: &mut Game| game.load_new_board(1, 0, 0, 0, 0, Seed::with_seed(1));
+        game.with_history(None, load_board).unwrap();
+        game.with_history(None, load_board).unwrap();
+        assert_eq!(game.next_board_id, 2);
+        game.undo().unwrap();
+        assert_eq!(game.next_board_id, 1);
+        assert_eq!(game.serialize_summary()["board_number"], json!(1));
+        game.redo().unwrap();
+        assert_eq!(game.serialize_summary()["board_number"], json!(2));
+    }
+
+    #[test]
+    fn keeps_players_who_joined_since() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        game.with_history(None, |game| game.set_player_score(&alice, 200))
+            .unwrap();
+
+        let bob = join(&mut game, "bob");
+        game.with_history(None, |game| game.set_player_score(&bob, 600))
+            .unwrap();
+
+        game.undo().unwrap();
+        assert_eq!(score(&game, &bob), 0);
+        game.undo().unwrap();
+        assert_eq!(score(&game, &alice), 0);
+        assert_eq!(score(&game, &bob), 0);
+
+        game.redo().unwrap();
+        game.redo().unwrap();
+        assert_eq!(score(&game, &alice), 200);
+        assert_eq!(score(&game, &bob), 600);
+    }
+
+    #[test]
+    fn forgets_history_when_players_leave() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        let bob = join(&mut game, "bob");
+        game.with_history(None, |game| game.set_player_score(&bob, 200))
+            .unwrap();
+
+        game.kick_player(bob.clone(), false).unwrap();
+        assert!(!game.players.contains_key(&bob));
+        assert!(matches!(game.undo(), Err(Error::NoHistory)));
+
+        game.with_history(None, |game| game.set_player_score(&alice, 200))
+            .unwrap();
+        game.undo().unwrap();
+        game.remove_player(alice.clone());
+        assert!(matches!(game.redo(), Err(Error::NoHistory)));
+        assert!(game.players.is_empty());
+    }
+
+    #[test]
+    fn judges_final_jeopardy_once() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        game.players.get_mut(&alice).unwrap().score = 1000;
+        game.players.get_mut(&alice).unwrap().final_jeopardy_info = FinalJeopardyInfo {
+            eligible: true,
+            wager: Some(600),
+            answer: Some("What is Ottawa?".into()),
+            ..Default::default()
+        };
+        game.state = GameState::FinalJeopardy {
+            category_name: "WORLD CAPITALS".into(),
+            question: Clue {
+                text: Some("It's the capital of Canada".into()),
+                link: None,
+            },
+            air_year: 2000,
+            answer: "Ottawa".into(),
+            question_revealed: true,
+            answers_locked: false,
+            reveal_order: vec![alice.clone()],
+            next_reveal: 0,
+        };
+
+        // Answers can't be judged until they're locked in and revealed
+        assert!(matches!(
+            game.evaluate_final_jeopardy_answer(&alice, AnswerType::Correct),
+            Err(Error::NotAllowed)
+        ));
+        game.lock_final_jeopardy_answers().unwrap();
+        assert!(matches!(
+            game.evaluate_final_jeopardy_answer(&alice, AnswerType::Correct),
+            Err(Error::NotAllowed)
+        ));
+        game.reveal_final_jeopardy_info(&alice, FinalJeopardyInfoType::Answer)
+            .unwrap();
+
+        game.evaluate_final_jeopardy_answer(&alice, AnswerType::Correct)
+            .unwrap();
+        assert_eq!(score(&game, &alice), 1600);
+
+        // Nor can they be judged twice
+        assert!(matches!(
+            game.evaluate_final_jeopardy_answer(&alice, AnswerType::Incorrect),
+            Err(Error::NotAllowed)
+        ));
+        assert_eq!(score(&game, &alice), 1600);
+    }
+
+    #[test]
+    fn logs_events() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        game.with_history(None, |game| game.set_player_score(&alice, 400))
+            .unwrap();
+        game.undo().unwrap();
+        // Failed actions don't leave events behind
+        assert!(game
+            .with_history(None, |game| game
+                .set_player_score(&PlayerId(Uuid::new_v4()), 1))
+            .is_err());
+
+        let events = game
+            .get_events_since(0)
+            .iter()
+            .map(LoggedEvent::serialize)
+            .collect::<Vec<_>>();
+        let types = events
+            .iter()
+            .map(|event| event["type"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [json!("PlayerJoined"), json!("ScoreChanged"), json!("Undo")]
+        );
+        assert_eq!(game.get_events_since(2).len(), 1);
+        assert!(game.get_events_since(10).is_empty());
+
+        let alice = alice.to_string();
+        assert_eq!(events[0]["index"], json!(0));
+        assert_eq!(events[0]["player"], json!(alice));
+        assert_eq!(events[0]["name"], json!("alice"));
+        assert_eq!(events[1]["index"], json!(1));
+        assert_eq!(events[1]["old_score"], json!(0));
+        assert_eq!(events[1]["new_score"], json!(400));
+        assert_eq!(events[2]["scores"], json!({ alice: 0 }));
+
+        // Archives read back exactly what clients were sent
+        for event in events {
+            let logged: LoggedEvent =
+                serde_json::from_value(WampPayloadValue::Object(event.clone())).unwrap();
+            assert_eq!(logged.serialize(), event);
+        }
+    }
+
+    #[test]
+    fn salts_passwords() {
+        let mut game = new_game();
+        let mut other = new_game();
+        assert!(game.check_password(None));
+
+        game.set_password(Some("hunter2"));
+        other.set_password(Some("hunter2"));
+        assert!(game.check_password(Some("hunter2")));
+        assert!(!game.check_password(Some("hunter3")));
+        assert!(!game.check_password(None));
+        assert_ne!(game.password, other.password);
+
+        game.set_password(Some(""));
+        assert!(!game.has_password());
+    }
+
+    #[test]
+    fn counts_late_buzzes() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        let bob = join(&mut game, "bob");
+        game.state = GameState::WaitingForAnswer {
+            board: Box::new(DUMMY_BOARD),
+            location: Location::new(0, 0).unwrap(),
+            controller: alice.clone(),
+            active_player: alice,
+            value: 200,
+        };
+
+        let version = game.version;
+        game.buzz(bob.clone()).unwrap();
+        assert!(game.version > version);
+        assert_eq!(
+            game.players[&bob].stats.serialize()["buzz_attempts"],
+            json!(1)
+        );
+        let events = game.get_events_since(0).len();
+        assert!(matches!(
+            game.get_events_since(0).last().unwrap().event,
+            GameEvent::LateBuzz { .. }
+        ));
+
+        // Only once per clue
+        game.buzz(bob.clone()).unwrap();
+        assert_eq!(game.get_events_since(0).len(), events);
+        assert_eq!(
+            game.players[&bob].stats.serialize()["buzz_attempts"],
+            json!(1)
+        );
+    }
+
+    #[test]
+    fn bans_by_avatar_and_profile() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        let mut bob = Player::new("bob".into(), "/avatars/bob.png".into());
+        bob.set_profile(Some("bob".into()));
+        let (bob, _) = game.add_player(bob).unwrap();
+
+        game.kick_player(alice, true).unwrap();
+        game.kick_player(bob, true).unwrap();
+
+        let same_avatar = Player::new("alicia".into(), "/avatars/alice.png".into());
+        assert!(game.is_banned(&same_avatar));
+        let mut same_profile = Player::new("bob".into(), "/avatars/new.png".into());
+        same_profile.set_profile(Some("bob".into()));
+        assert!(game.is_banned(&same_profile));
+        assert!(!game.is_banned(&Player::new("carol".into(), "/avatars/new.png".into())));
+    }
+
+    #[test]
+    fn caps_the_waiting_room() {
+        let mut game = new_game();
+        game.set_join_rules(None, true);
+        for i in 0..super::MAX_WAITING_PLAYERS {
+            let name = format!("player{}", i);
+            assert!(game
+                .add_player(Player::new(name.clone(), format!("/avatars/{}.png", name)))
+                .is_ok());
+        }
+        assert!(matches!(
+            game.add_player(Player::new("late".into(), "/avatars/late.png".into())),
+            Err(Error::WaitingRoomFull)
+        ));
+    }
+
+    #[test]
+    fn hides_invite_code_from_spectators() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        let invite_code = json!(game.invite_code.to_string());
+
+        assert!(!game.serialize(false).contains_key("invite_code"));
+        assert_eq!(game.serialize(true)["invite_code"], invite_code);
+        match &game.serialize_for_player(&alice)["private"] {
+            WampPayloadValue::Object(private) => {
+                assert_eq!(private["invite_code"], invite_code)
+            }
+            other => panic!("unexpected private view: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drops_quiet_spectators() {
+        let mut game = new_game();
+        let quiet = game.add_spectator().unwrap();
+        let chatty = game.add_spectator().unwrap();
+        let later = Utc::now() + Duration::minutes(2);
+        game.spectators.insert(chatty.clone(), later);
+
+        let version = game.version;
+        assert_eq!(game.prune_spectators(later, Duration::minutes(1)), 1);
+        assert!(game.version > version);
+        assert!(!game.refresh_spectator(&quiet));
+        assert!(game.refresh_spectator(&chatty));
+        assert_eq!(game.get_spectator_count(), 1);
+    }
+
+    #[test]
+    fn versions_every_change() {
+        let mut game = new_game();
+        let alice = join(&mut game, "alice");
+        let mut version = game.version;
+        let mut check_bumped = |game: &Game| {
+            assert!(game.version > version);
+            version = game.version;
+        };
+
+        game.set_join_rules(None, true);
+        check_bumped(&game);
+        let (bob, _) = game
+            .add_player(Player::new("bob".into(), "/avatars/bob.png".into()))
+            .unwrap();
+        check_bumped(&game);
+        game.approve_join(&bob, false).unwrap();
+        check_bumped(&game);
+        let (carol, _) = game
+            .add_player(Player::new("carol".into(), "/avatars/carol.png".into()))
+            .unwrap();
+        check_bumped(&game);
+        assert!(game.remove_player(carol));
+        check_bumped(&game);
+
+        game.set_private(true);
+        check_bumped(&game);
+        game.set_password(Some("hunter2"));
+        check_bumped(&game);
+        let spectator = game.add_spectator().unwrap();
+        check_bumped(&game);
+        assert!(game.remove_spectator(&spectator));
+        check_bumped(&game);
+
+        // Failed actions roll the version back, even if they got partway
+        let last_activity = game.last_activity;
+        assert!(game
+            .with_history(None, |game| {
+                game.set_player_score(&alice, 100)?;
+                Err(Error::NotAllowed)
+            })
+            .is_err());
+        assert_eq!(game.version, version);
+        assert_eq!(game.last_activity, last_activity);
+        assert_eq!(score(&game, &alice), 0);
+
+        // Boards that couldn't be made don't use up a number
+        assert!(matches!(
+            game.with_history(None, |game| game.load_new_board(
+                1,
+                1,
+                0,
+                0,
+                0,
+                Seed::with_seed(1)
+            )),
+            Err(Error::TooManyDailyDoubles)
+        ));
+        assert_eq!(game.next_board_id, 0);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use wamp_async::{WampKwArgs, WampPayloadValue};
+
+/// How one player did in one category.
+#[derive(Debug, Default, Clone)]
+struct CategoryStats {
+    correct: u32,
+    incorrect: u32,
+    winnings: i64,
+}
+
+/// Running totals for one player, updated as their answers are judged.
+#[derive(Debug, Default, Clone)]
+pub struct PlayerStats {
+    correct: u32,
+    incorrect: u32,
+    buzz_attempts: u32,
+    buzz_wins: u32,
+    daily_doubles_correct: u32,
+    daily_doubles_incorrect: u32,
+    daily_double_winnings: i64,
+    coryat: i64, // score ignoring wagers: Daily Doubles count at face value if right, nothing if wrong
+    categories: HashMap<String, CategoryStats>,
+}
+impl PlayerStats {
+    /// Records a buzz. Each clue only counts as one attempt, so `new_attempt` is false when the
+    /// player already buzzed on this clue (and lost the race to someone who then got it wrong).
+    pub fn record_buzz(&mut self, new_attempt: bool, won: bool) {
+        if new_attempt {
+            self.buzz_attempts += 1;
+        }
+        if won {
+            self.buzz_wins += 1;
+        }
+    }
+
+    /// Records a judged answer. `value` is what the player won or lost, and `face_value` is what
+    /// the square is worth on the board (these only differ for Daily Doubles).
+    pub fn record_answer(
+        &mut self,
+        category: &str,
+        correct: bool,
+        value: i64,
+        face_value: i64,
+        is_daily_double: bool,
+    ) {
+        let category = self.categories.entry(category.to_string()).or_default();
+        if correct {
+            self.correct += 1;
+            category.correct += 1;
+            category.winnings += value;
+        } else {
+            self.incorrect += 1;
+            category.incorrect += 1;
+            category.winnings -= value;
+        }
+
+        if is_daily_double {
+            if correct {
+                self.daily_doubles_correct += 1;
+                self.daily_double_winnings += value;
+                self.coryat += face_value;
+            } else {
+                self.daily_doubles_incorrect += 1;
+                self.daily_double_winnings -= value;
+            }
+        } else if correct {
+            self.coryat += face_value;
+        } else {
+            self.coryat -= face_value;
+        }
+    }
+
+    pub fn get_correct(&self) -> u32 {
+        self.correct
+    }
+
+    pub fn get_incorrect(&self) -> u32 {
+        self.incorrect
+    }
+
+    pub fn get_coryat(&self) -> i64 {
+        self.coryat
+    }
+
+    pub fn serialize(&self) -> WampKwArgs {
+        let mut result = WampKwArgs::new();
+        for (key, count) in &[
+            ("clues_attempted", self.correct + self.incorrect),
+            ("correct", self.correct),
+            ("incorrect", self.incorrect),
+            ("buzz_attempts", self.buzz_attempts),
+            ("buzz_wins", self.buzz_wins),
+            ("daily_doubles_correct", self.daily_doubles_correct),
+            ("daily_doubles_incorrect", self.daily_doubles_incorrect),
+        ] {
+            result.insert((*key).into(), WampPayloadValue::Number((*count).into()));
+        }
+        result.insert(
+            "daily_double_winnings".into(),
+            WampPayloadValue::String(self.daily_double_winnings.to_string()),
+        );
+        result.insert(
+            "coryat".into(),
+            WampPayloadValue::String(self.coryat.to_string()),
+        );
+        result.insert(
+            "buzz_win_rate".into(),
+            if self.buzz_attempts > 0 {
+                serde_json::Number::from_f64(self.buzz_wins as f64 / self.buzz_attempts as f64)
+                    .map_or(WampPayloadValue::Null, WampPayloadValue::Number)
+            } else {
+                WampPayloadValue::Null
+            },
+        );
+        result.insert(
+            "categories".into(),
+            WampPayloadValue::Object(
+                self.categories
+                    .iter()
+                    .map(|(title, stats)| {
+                        (
+                            title.clone(),
+                            WampPayloadValue::Object({
+                                let mut category = wamp_dict! {
+                                    "winnings" => stats.winnings.to_string(),
+                                };
+                                category.insert(
+                                    "correct".into(),
+                                    WampPayloadValue::Number(stats.correct.into()),
+                                );
+                                category.insert(
+                                    "incorrect".into(),
+                                    WampPayloadValue::Number(stats.incorrect.into()),
+                                );
+                                category
+                            }),
+                        )
+                    })
+                    .collect(),
+            ),
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn coryat_ignores_wagers() {
+        let mut stats = PlayerStats::default();
+        stats.record_answer("POTENT POTABLES", true, 400, 400, false);
+        stats.record_answer("POTENT POTABLES", false, 200, 200, false);
+        stats.record_answer("WORLD CAPITALS", true, 1500, 800, true);
+        stats.record_answer("WORLD CAPITALS", false, 1000, 600, true);
+
+        assert_eq!(stats.correct, 2);
+        assert_eq!(stats.incorrect, 2);
+        assert_eq!(stats.coryat, 400 - 200 + 800);
+        assert_eq!(stats.daily_double_winnings, 500);
+        assert_eq!(stats.categories["WORLD CAPITALS"].winnings, 500);
+    }
+
+    #[test]
+    fn buzzes_count_once_per_clue() {
+        let mut stats = PlayerStats::default();
+        stats.record_buzz(true, false);
+        stats.record_buzz(false, true);
+        stats.record_buzz(true, true);
+
+        assert_eq!(stats.buzz_attempts, 2);
+        assert_eq!(stats.buzz_wins, 2);
+    }
+}
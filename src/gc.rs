@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::*;
+use serde::Deserialize;
+
+use crate::{
+    config::deserialize_secs, publish_notice, ARCHIVE_MANAGER, GAME_LIMITER, INVITE_LIMITER,
+    JOIN_LIMITER, NEW_GAME_LIMITER, PLAYER_LIMITER, PROFILE_LIMITER, STATE,
+};
+
+/// The longest any of the garbage collector's settings can be.
+pub const MAX_DURATION: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// How the garbage collector decides which games to clean up. A game is removed once nothing has
+/// happened in it for `idle_timeout`, and everyone in it is warned `warning_period` beforehand.
+/// The warning can only go out on time if `interval` is shorter than `warning_period`. Spectators
+/// who haven't checked in for `spectator_timeout` stop being counted.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GcConfig {
+    #[serde(rename = "interval_secs", deserialize_with = "deserialize_secs")]
+    pub interval: Duration,
+    #[serde(rename = "idle_timeout_secs", deserialize_with = "deserialize_secs")]
+    pub idle_timeout: Duration,
+    #[serde(rename = "warning_secs", deserialize_with = "deserialize_secs")]
+    pub warning_period: Duration,
+    #[serde(
+        rename = "spectator_timeout_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub spectator_timeout: Duration,
+}
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            interval: Duration::from_secs(30 * 60),
+            idle_timeout: Duration::from_secs(24 * 60 * 60),
+            warning_period: Duration::from_secs(60 * 60),
+            spectator_timeout: Duration::from_secs(2 * 60),
+        }
+    }
+}
+
+// Settings are checked against `MAX_DURATION` when they're loaded, so this never overflows.
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::seconds(duration.min(MAX_DURATION).as_secs() as i64)
+}
+
+/// Where a game stands with the garbage collector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Expiry {
+    Active,
+    Warn { expires_at: DateTime<Utc> },
+    Expired,
+}
+
+/// Works out where a game stands, going by when anything last happened in it.
+pub(crate) fn get_expiry(
+    last_activity: DateTime<Utc>,
+    now: DateTime<Utc>,
+    config: &GcConfig,
+) -> Expiry {
+    let idle_timeout = to_chrono(config.idle_timeout);
+    let warning_period = to_chrono(config.warning_period);
+
+    let expires_at = last_activity + idle_timeout;
+    if now >= expires_at {
+        Expiry::Expired
+    } else if now >= expires_at - warning_period {
+        Expiry::Warn { expires_at }
+    } else {
+        Expiry::Active
+    }
+}
+
+/// Checks every game once: warns the ones about to expire, and archives and removes the ones
+/// which have. Removing a game takes it out of the lobby as well.
+async fn collect(config: &GcConfig) {
+    info!("GC running...");
+    let now = Utc::now();
+    let mut removed = 0;
+
+    let spectator_timeout = to_chrono(config.spectator_timeout);
+    for (game_id, handle) in STATE.get_handles() {
+        let config = config.clone();
+        let transcript_game_id = game_id.clone();
+        let result = handle
+            .run(move |game| {
+                let dropped = game.prune_spectators(now, spectator_timeout);
+                if dropped > 0 {
+                    debug!("Dropped {} spectators who stopped checking in", dropped);
+                }
+
+                match game.check_expiry(now, &config) {
+                    Expiry::Active => None,
+                    Expiry::Warn { expires_at } => {
+                        publish_notice(
+                            &game.notification_channel,
+                            "ExpiryWarning",
+                            Some(expires_at),
+                        );
+                        None
+                    }
+                    Expiry::Expired => {
+                        publish_notice(&game.notification_channel, "Expired", None);
+                        Some(game.make_transcript(&transcript_game_id))
+                    }
+                }
+            })
+            .await;
+
+        match result {
+            Ok(Some(transcript)) => {
+                STATE.remove_game(&game_id);
+                removed += 1;
+
+                // Games which never got going aren't worth keeping.
+                if transcript.got_going() {
+                    if let Err(err) = ARCHIVE_MANAGER.save(&transcript).await {
+                        warn!("Failed to archive expired game {:?}: {:?}", game_id, err);
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(err) => warn!("GC could not check game {:?}: {:?}", game_id, err),
+        }
+    }
+
+    info!("GC done; removed {} games.", removed);
+
+    NEW_GAME_LIMITER.prune();
+    PLAYER_LIMITER.prune();
+    GAME_LIMITER.prune();
+    JOIN_LIMITER.prune();
+    PROFILE_LIMITER.prune();
+    INVITE_LIMITER.prune();
+}
+
+/// Runs the garbage collector forever.
+pub async fn run(config: GcConfig) {
+    loop {
+        tokio::time::sleep(config.interval).await;
+        collect(&config).await;
+    }
+}
+
+#[cfg(test)]
+mod gc_tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use super::{get_expiry, Expiry, GcConfig};
+
+    #[test]
+    fn warns_before_expiring() {
+        let config = GcConfig {
+            interval: Duration::from_secs(60),
+            idle_timeout: Duration::from_secs(60 * 60),
+            warning_period: Duration::from_secs(10 * 60),
+            spectator_timeout: Duration::from_secs(2 * 60),
+        };
+        let last_activity = Utc::now();
+        let expires_at = last_activity + chrono::Duration::minutes(60);
+
+        assert_eq!(
+            get_expiry(last_activity, last_activity, &config),
+            Expiry::Active
+        );
+        assert_eq!(
+            get_expiry(
+                last_activity,
+                last_activity + chrono::Duration::minutes(49),
+                &config
+            ),
+            Expiry::Active
+        );
+        assert_eq!(
+            get_expiry(
+                last_activity,
+                last_activity + chrono::Duration::minutes(50),
+                &config
+            ),
+            Expiry::Warn { expires_at }
+        );
+        assert_eq!(
+            get_expiry(last_activity, expires_at, &config),
+            Expiry::Expired
+        );
+    }
+}
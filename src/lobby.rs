@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use wamp_async::{WampKwArgs, WampPayloadValue};
+
+use crate::GameId;
+
+/// Which page of the lobby to return, and which games to leave out of it.
+#[derive(Debug, Clone)]
+pub struct LobbyQuery {
+    pub offset: usize,
+    pub limit: usize,
+    /// Only games whose moderator or one of whose players has this in their name.
+    pub search: Option<String>,
+    /// Only games with (or without) a password.
+    pub has_password: Option<bool>,
+    /// Only games in this phase (like "NotStarted").
+    pub phase: Option<String>,
+    /// Only games someone could (or couldn't) be seated in right away.
+    pub joinable: Option<bool>,
+}
+
+/// The index of games listed in the lobby. Each game's task keeps its own entry up to date, so
+/// listing games never has to wait on the games themselves. Every change to the index gets a new
+/// version, so clients can tell whether they missed an event and need to fetch the list again.
+pub struct Lobby {
+    index: RwLock<LobbyIndex>,
+}
+
+struct LobbyIndex {
+    version: u64,
+    entries: HashMap<GameId, LobbyEntry>,
+}
+
+struct LobbyEntry {
+    time_started: DateTime<Utc>,
+    listing: WampKwArgs,
+}
+impl LobbyEntry {
+    fn get_flag(&self, name: &str) -> bool {
+        self.listing
+            .get(name)
+            .and_then(WampPayloadValue::as_bool)
+            .unwrap_or(false)
+    }
+
+    fn matches(&self, query: &LobbyQuery) -> bool {
+        if query
+            .has_password
+            .is_some_and(|has_password| self.get_flag("has_password") != has_password)
+        {
+            return false;
+        }
+        if query
+            .joinable
+            .is_some_and(|joinable| self.get_flag("joinable") != joinable)
+        {
+            return false;
+        }
+        if let Some(phase) = &query.phase {
+            if self.listing.get("phase").and_then(WampPayloadValue::as_str) != Some(phase.as_str())
+            {
+                return false;
+            }
+        }
+
+        if let Some(search) = &query.search {
+            let search = search.to_lowercase();
+            let moderator = self.listing.get("moderator").into_iter();
+            let players = self
+                .listing
+                .get("players")
+                .and_then(WampPayloadValue::as_array)
+                .into_iter()
+                .flatten();
+            let found = moderator
+                .chain(players)
+                .filter_map(WampPayloadValue::as_str)
+                .any(|name| name.to_lowercase().contains(&search));
+            if !found {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Lobby {
+    pub fn new() -> Self {
+        Lobby {
+            index: RwLock::new(LobbyIndex {
+                version: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Sets a game's entry, where a `None` listing takes it out of the lobby. Returns the event to
+    /// publish on the lobby channel, or `None` if nothing changed.
+    pub fn update(
+        &self,
+        game_id: &GameId,
+        time_started: DateTime<Utc>,
+        listing: Option<WampKwArgs>,
+    ) -> Option<WampKwArgs> {
+        let mut listing = match listing {
+            Some(listing) => listing,
+            None => return self.remove(game_id),
+        };
+        listing.insert(
+            "game_id".to_string(),
+            WampPayloadValue::String(game_id.to_string()),
+        );
+
+        let mut index = self.index.write();
+        let event = match index.entries.get(game_id) {
+            Some(entry) if entry.listing == listing => return None,
+            Some(_) => "game_updated",
+            None => "game_added",
+        };
+
+        index.version += 1;
+        let version = index.version;
+        let mut result = wamp_dict! {
+            "event" => event.to_string(),
+        };
+        result.insert(
+            "game".to_string(),
+            WampPayloadValue::Object(listing.clone()),
+        );
+        result.insert(
+            "version".to_string(),
+            WampPayloadValue::Number(version.into()),
+        );
+
+        index.entries.insert(
+            game_id.clone(),
+            LobbyEntry {
+                time_started,
+                listing,
+            },
+        );
+        Some(result)
+    }
+
+    /// Takes a game out of the lobby. Returns the event to publish on the lobby channel, or `None`
+    /// if it wasn't listed.
+    pub fn remove(&self, game_id: &GameId) -> Option<WampKwArgs> {
+        let mut index = self.index.write();
+        index.entries.remove(game_id)?;
+        index.version += 1;
+
+        let mut result = wamp_dict! {
+            "event" => "game_removed".to_string(),
+            "game_id" => game_id.to_string(),
+        };
+        result.insert(
+            "version".to_string(),
+            WampPayloadValue::Number(index.version.into()),
+        );
+        Some(result)
+    }
+
+    /// Gets one page of the games matching a query, newest first, along with how many matched in
+    /// total and the version of the index they came from.
+    pub fn list(&self, query: &LobbyQuery) -> WampKwArgs {
+        let index = self.index.read();
+
+        let mut entries = index
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.matches(query))
+            .collect::<Vec<_>>();
+        entries.sort_by(|(a_id, a), (b_id, b)| {
+            b.time_started
+                .cmp(&a.time_started)
+                .then_with(|| a_id.0.cmp(&b_id.0))
+        });
+
+        let total = entries.len();
+        let games = entries
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit)
+            .map(|(_, entry)| WampPayloadValue::Object(entry.listing.clone()))
+            .collect();
+
+        let mut result = WampKwArgs::new();
+        result.insert("games".to_string(), WampPayloadValue::Array(games));
+        result.insert("total".to_string(), WampPayloadValue::Number(total.into()));
+        result.insert(
+            "offset".to_string(),
+            WampPayloadValue::Number(query.offset.into()),
+        );
+        result.insert(
+            "version".to_string(),
+            WampPayloadValue::Number(index.version.into()),
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod lobby_tests {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+    use wamp_async::{WampKwArgs, WampPayloadValue};
+
+    use super::{Lobby, LobbyQuery};
+    use crate::GameId;
+
+    fn listing(moderator: &str, has_password: bool) -> WampKwArgs {
+        let mut listing = wamp_dict! {
+            "moderator" => moderator.to_string(),
+            "phase" => if has_password { "Round" } else { "NotStarted" }.to_string(),
+        };
+        listing.insert("players".to_string(), WampPayloadValue::Array(vec![]));
+        listing.insert(
+            "has_password".to_string(),
+            WampPayloadValue::Bool(has_password),
+        );
+        listing
+    }
+
+    fn query() -> LobbyQuery {
+        LobbyQuery {
+            offset: 0,
+            limit: 10,
+            search: None,
+            has_password: None,
+            phase: None,
+            joinable: None,
+        }
+    }
+
+    fn event_name(event: &WampKwArgs) -> &str {
+        event["event"].as_str().unwrap()
+    }
+
+    #[test]
+    fn publishes_only_changes() {
+        let lobby = Lobby::new();
+        let game_id = GameId(Uuid::new_v4());
+        let now = Utc::now();
+
+        let added = lobby
+            .update(&game_id, now, Some(listing("Alex", false)))
+            .unwrap();
+        assert_eq!(event_name(&added), "game_added");
+        assert_eq!(added["version"], 1);
+
+        assert!(lobby
+            .update(&game_id, now, Some(listing("Alex", false)))
+            .is_none());
+
+        let updated = lobby
+            .update(&game_id, now, Some(listing("Alex", true)))
+            .unwrap();
+        assert_eq!(event_name(&updated), "game_updated");
+
+        // Going private takes the game out of the lobby, and back in once it's public again.
+        let removed = lobby.update(&game_id, now, None).unwrap();
+        assert_eq!(event_name(&removed), "game_removed");
+        assert!(lobby.update(&game_id, now, None).is_none());
+        assert!(lobby.remove(&game_id).is_none());
+
+        let added = lobby
+            .update(&game_id, now, Some(listing("Alex", true)))
+            .unwrap();
+        assert_eq!(event_name(&added), "game_added");
+        assert_eq!(added["version"], 4);
+    }
+
+    #[test]
+    fn pages_and_filters() {
+        let lobby = Lobby::new();
+        let now = Utc::now();
+        for i in 0..5 {
+            lobby.update(
+                &GameId(Uuid::new_v4()),
+                now - Duration::minutes(i),
+                Some(listing(&format!("Moderator {}", i), i % 2 == 0)),
+            );
+        }
+
+        let page = lobby.list(&LobbyQuery {
+            offset: 1,
+            limit: 2,
+            ..query()
+        });
+        assert_eq!(page["total"], 5);
+        let games = page["games"].as_array().unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0]["moderator"], "Moderator 1");
+        assert_eq!(games[1]["moderator"], "Moderator 2");
+
+        let locked = lobby.list(&LobbyQuery {
+            has_password: Some(true),
+            ..query()
+        });
+        assert_eq!(locked["total"], 3);
+
+        let not_started = lobby.list(&LobbyQuery {
+            phase: Some("NotStarted".to_string()),
+            ..query()
+        });
+        assert_eq!(not_started["total"], 2);
+
+        let searched = lobby.list(&LobbyQuery {
+            search: Some("moderator 3".to_string()),
+            ..query()
+        });
+        assert_eq!(searched["total"], 1);
+        assert_eq!(searched["games"][0]["moderator"], "Moderator 3");
+    }
+}
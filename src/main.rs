@@ -1,61 +1,129 @@
-use std::{borrow::Cow, collections::HashMap, env, fmt, path::PathBuf, time::Duration};
+use std::{borrow::Cow, collections::HashMap, env, fmt, str::FromStr, sync::Arc};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use futures::lock::Mutex;
 use log::*;
 use once_cell::sync::OnceCell;
 use parking_lot::RwLock;
-use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    signal,
+    sync::{mpsc, watch},
+};
 use uuid::Uuid;
 use wamp_async::{WampKwArgs, WampPayloadValue};
 
 #[macro_use]
 mod util;
 
+mod actor;
+mod admin;
+mod archive;
 mod avatar;
+mod config;
+mod connection;
 mod data;
 mod errors;
 mod game;
+mod gc;
+mod lobby;
+mod metrics;
+mod names;
+mod profiles;
+mod rate_limit;
 mod seed;
 mod server;
 
+use actor::GameHandle;
+use archive::ArchiveManager;
 use avatar::AvatarManager;
+use config::{Args, Config};
 use errors::Error;
+use lobby::Lobby;
+use metrics::Metrics;
+use profiles::ProfileManager;
+use rate_limit::RateLimiter;
+use seed::Seed;
 
 lazy_static::lazy_static! {
     static ref STATE: JeopardyState = JeopardyState {
         games: RwLock::new(HashMap::new()),
+        invite_codes: RwLock::new(HashMap::new()),
     };
 
     static ref MSG_QUEUE: OnceCell<mpsc::UnboundedSender<Message>> = OnceCell::new();
 
-    static ref JEOPARDY_DATA: OnceCell<data::JeopardyData> = OnceCell::new();
+    // Replaced wholesale when an operator reloads the dataset. Boards already dealt keep their
+    // clues, since they hold copies.
+    static ref JEOPARDY_DATA: RwLock<Arc<data::JeopardyData>> = RwLock::new(Default::default());
 
-    static ref AVATAR_DIRECTORY: PathBuf = {
-        let mut buf = PathBuf::new();
-        buf.push("static");
-        buf.push("avatars");
-        buf
+    static ref CONFIG: OnceCell<Config> = OnceCell::new();
+
+    static ref STARTED_AT: DateTime<Utc> = Utc::now();
+
+    // Creating games isn't tied to any existing player or game, so it's limited server-wide.
+    static ref NEW_GAME_LIMITER: RateLimiter<()> = {
+        let limits = &CONFIG.get().unwrap().limits;
+        RateLimiter::new(limits.new_game_burst, limits.new_game_period)
+    };
+
+    // Registering profiles isn't either, but gets its own limit so it can't crowd out new games.
+    static ref PROFILE_LIMITER: RateLimiter<()> = {
+        let limits = &CONFIG.get().unwrap().limits;
+        RateLimiter::new(limits.profile_burst, limits.profile_period)
+    };
+
+    // Invite codes are short enough to guess, so looking them up is limited server-wide too.
+    static ref INVITE_LIMITER: RateLimiter<()> = {
+        let limits = &CONFIG.get().unwrap().limits;
+        RateLimiter::new(limits.invite_burst, limits.invite_period)
+    };
+
+    // The router doesn't tell us who's calling, so calls are only charged to a player and their
+    // game once they've authenticated; otherwise anyone could use up someone else's limits.
+    static ref PLAYER_LIMITER: RateLimiter<PlayerId> = {
+        let limits = &CONFIG.get().unwrap().limits;
+        RateLimiter::new(limits.player_call_burst, limits.player_call_period)
+    };
+
+    static ref GAME_LIMITER: RateLimiter<GameId> = {
+        let limits = &CONFIG.get().unwrap().limits;
+        RateLimiter::new(limits.game_call_burst, limits.game_call_period)
     };
 
-    static ref AVATAR_MANAGER: Mutex<AvatarManager> = Mutex::new(AvatarManager::new(
-        AVATAR_DIRECTORY.clone(),
-        "avatars".into(),
-        MAX_AVATAR_SIZE
-    ).unwrap());
+    // Joining and spectating happen before there's anyone to authenticate, so they're limited per
+    // game, separately from the calls made while playing.
+    static ref JOIN_LIMITER: RateLimiter<GameId> = {
+        let limits = &CONFIG.get().unwrap().limits;
+        RateLimiter::new(limits.join_burst, limits.join_period)
+    };
+
+    static ref LOBBY: Lobby = Lobby::new();
+
+    static ref METRICS: Metrics = Metrics::new();
+
+    static ref ARCHIVE_MANAGER: ArchiveManager =
+        ArchiveManager::new(CONFIG.get().unwrap().paths.archive.clone());
+
+    static ref AVATAR_MANAGER: Mutex<AvatarManager> = {
+        let config = CONFIG.get().unwrap();
+        Mutex::new(AvatarManager::new(
+            config.paths.avatars.clone(),
+            config.paths.avatar_url_prefix.clone(),
+            config.limits.max_avatar_size,
+        ).unwrap())
+    };
+
+    static ref PROFILE_MANAGER: Mutex<ProfileManager> = Mutex::new(
+        ProfileManager::load(CONFIG.get().unwrap().paths.profiles.clone())
+            .expect("Failed to load player profiles")
+    );
 }
 
-const OPERATION_TIMEOUT: Duration = Duration::from_secs(5);
-const GC_INTERVAL: Duration = Duration::from_secs(30 * 60);
-const GC_CLEANUP_THRESHOLD: Duration = Duration::from_secs(60 * 60 * 24);
-const ROUTER_PORT_ENV_NAME: &str = "JPDY_ROUTER_PORT";
-const WAMP_REALM: &str = "jpdy";
 const GAME_LOBBY_CHANNEL: &str = "jpdy.chan.lobby";
-const DATABASE_PATH: &str = "jeo_data_utf8.csv.gz";
-const MAX_AVATAR_SIZE: usize = 32 * 1024;
 
 /// A game's ID.
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct GameId(Uuid);
 impl fmt::Display for GameId {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -73,7 +141,7 @@ impl fmt::Display for AuthToken {
 }
 
 /// A player ID.
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PlayerId(Uuid);
 impl fmt::Display for PlayerId {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -81,8 +149,25 @@ impl fmt::Display for PlayerId {
     }
 }
 
+/// A short, human-friendly code which resolves to a game. Uses the same word encoding as board
+/// seeds.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct InviteCode(Seed);
+impl fmt::Display for InviteCode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+impl FromStr for InviteCode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        Ok(InviteCode(value.parse()?))
+    }
+}
+
 /// A message to be sent (typically from an RPC invocation)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Message {
     topic: Cow<'static, str>,
     args: Option<wamp_async::WampArgs>,
@@ -90,265 +175,394 @@ struct Message {
 }
 
 struct JeopardyState {
-    // Use a double RwLock here so that when mutating a game, we can take a read lock
-    // on the games instance, allowing multiple games to be mutated simultaneously. The only
-    // time a write lock is needed on the outer HashMap is when new games are added or old ones are
-    // removed. Other possible improvements include having multiple maps and choosing randomly
-    // which one to add to in order to further reduce the chance of lock contention.
-    games: RwLock<HashMap<GameId, RwLock<game::Game>>>,
+    // Each game runs on its own task (see `actor`), so this map only holds handles to them. It's
+    // only ever locked briefly, to look up, add or remove a handle, and never across an await.
+    games: RwLock<HashMap<GameId, GameHandle>>,
+
+    // Maps invite codes to the games they belong to. Always acquired after the games lock if both
+    // are needed.
+    invite_codes: RwLock<HashMap<InviteCode, GameId>>,
 }
 impl JeopardyState {
-    /// Deletes a game from the map. Will acquire the global game write lock.
-    fn remove_game(&self, game: &GameId) -> Result<(), Error> {
-        info!("removing game: {:?}", game);
+    /// Gets the handle for a game.
+    fn get_game(&self, game_id: &GameId) -> Result<GameHandle, Error> {
         self.games
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?
-            .remove(game);
-        Ok(())
+            .read()
+            .get(game_id)
+            .cloned()
+            .ok_or(Error::UnknownGame)
+    }
+
+    /// Gets a snapshot of every game's handle.
+    fn get_handles(&self) -> Vec<(GameId, GameHandle)> {
+        self.games
+            .read()
+            .iter()
+            .map(|(game_id, handle)| (game_id.clone(), handle.clone()))
+            .collect()
+    }
+
+    /// Runs `f` on the given game's task and returns its result. Commands for the same game run
+    /// one at a time, in the order they were sent.
+    pub async fn with_game<F, T>(&self, game_id: &GameId, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut game::Game) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.get_game(game_id)?.run(f).await
+    }
+
+    /// Deletes a game from the map and closes its task, which takes it out of the lobby once any
+    /// commands already sent to it are done.
+    fn remove_game(&self, game: &GameId) {
+        info!("removing game: {:?}", game);
+        let removed = self.games.write().remove(game);
+
+        if let Some(removed) = removed {
+            self.invite_codes.write().remove(&removed.invite_code);
+            removed.close();
+        }
     }
 
-    /// Adds a game to the state. Will acquire (and release) the global game write lock.
-    pub fn add_game(
+    /// Adds a game to the state and starts its task. `configure` is called on the new game before
+    /// it's inserted, to apply any settings the moderator asked for.
+    pub fn add_game<F: FnOnce(&mut game::Game)>(
         &self,
         moderator_name: String,
         avatar_url: String,
-    ) -> Result<(GameId, PlayerId, AuthToken, String), Error> {
+        configure: F,
+    ) -> Result<(GameId, PlayerId, AuthToken, String, String, InviteCode), Error> {
         let game_id = GameId(Uuid::new_v4());
         let moderator = game::Player::new(moderator_name, avatar_url);
         let auth_token = moderator.get_auth();
-        let game = game::Game::new(moderator);
-        let user_id = game.moderator_id.clone();
-        let moderator_channel = game.moderator_state_channel.clone();
-
-        self.games
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?
-            .insert(game_id.clone(), RwLock::new(game));
 
-        info!("New game ({:?}) added to global state", game_id);
-        Ok((game_id, user_id, auth_token, moderator_channel))
-    }
+        let mut games = self.games.write();
+        if games.len() >= CONFIG.get().unwrap().limits.max_games {
+            return Err(Error::TooManyGames);
+        }
 
-    /// Gets the list of open games. Acquires the global game read lock, and each game's read lock
-    /// as well.
-    pub fn get_games(&self) -> Result<WampKwArgs, Error> {
-        let games = self
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
+        let mut invite_codes = self.invite_codes.write();
 
-        let games = games
-            .iter()
-            .filter_map(|(game_id, game)| {
-                let game = game.try_read_for(OPERATION_TIMEOUT)?;
-
-                let mut dict = wamp_dict! {
-                    "game_id" => game_id.to_string(),
-                    "moderator" => game.get_moderator_name().into(),
-                    "moderator_avatar" => game.get_moderator_avatar_url().into(),
-                    "channel" => game.player_state_channel.clone(),
-                };
-                let players = WampPayloadValue::Array(
-                    game.get_player_names()
-                        .iter()
-                        .map(|name| WampPayloadValue::String((*name).to_string()))
-                        .collect(),
-                );
-                dict.insert("players".to_string(), players);
-
-                Some(WampPayloadValue::Object(dict))
-            })
-            .collect::<Vec<_>>();
+        let invite_code = loop {
+            let code = InviteCode(Seed::new_random());
+            if !invite_codes.contains_key(&code) {
+                break code;
+            }
+        };
 
-        let mut result = WampKwArgs::new();
-        result.insert("games".to_string(), WampPayloadValue::Array(games));
+        let mut game = game::Game::new(game_id.clone(), moderator, invite_code.clone());
+        configure(&mut game);
+        let user_id = game.moderator_id.clone();
+        let moderator_channel = game.moderator_state_channel.clone();
+        let notification_channel = game.notification_channel.clone();
 
-        result.insert(
-            "min_year".to_string(),
-            WampPayloadValue::Number(JEOPARDY_DATA.get().unwrap().min_year.into()),
-        );
-        result.insert(
-            "max_year".to_string(),
-            WampPayloadValue::Number(JEOPARDY_DATA.get().unwrap().max_year.into()),
-        );
+        invite_codes.insert(invite_code.clone(), game_id.clone());
+        games.insert(game_id.clone(), GameHandle::spawn(game_id.clone(), game));
 
-        Ok(result)
+        info!("New game ({:?}) added to global state", game_id);
+        Ok((
+            game_id,
+            user_id,
+            auth_token,
+            moderator_channel,
+            notification_channel,
+            invite_code,
+        ))
     }
 
-    /// Broadcasts a state update for the given game. If that game is over, remove it from the map.
-    pub async fn broadcast_game_state_update(&self, game_id: &GameId) -> Result<(), Error> {
-        info!("broadcast_game_state_update: {:?}", game_id);
+    /// Looks up the game an invite code belongs to.
+    pub fn resolve_invite_code(&self, invite_code: &InviteCode) -> Result<GameId, Error> {
+        self.invite_codes
+            .read()
+            .get(invite_code)
+            .cloned()
+            .ok_or(Error::UnknownInviteCode)
+    }
 
-        let is_ended = {
-            let games = self
-                .games
-                .try_read_for(OPERATION_TIMEOUT)
-                .ok_or(Error::LockTimeout)?;
-            let game = games
-                .get(game_id)
-                .ok_or(Error::UnknownGame)?
-                .try_read_for(OPERATION_TIMEOUT)
-                .ok_or(Error::LockTimeout)?;
-
-            let moderator_state = game.serialize(true);
-            let player_state = game.serialize(false);
-
-            MSG_QUEUE
-                .get()
-                .unwrap()
-                .send(Message {
-                    topic: Cow::Owned(game.moderator_state_channel.clone()),
-                    args: None,
-                    kwargs: Some(moderator_state),
-                })
-                .unwrap();
-
-            MSG_QUEUE
-                .get()
-                .unwrap()
-                .send(Message {
-                    topic: Cow::Owned(game.player_state_channel.clone()),
-                    args: None,
-                    kwargs: Some(player_state),
+    /// Lets everyone in every game know the server is going away, and archives the games that got
+    /// going (a board or Final Jeopardy was put up) so they can still be reviewed.
+    pub async fn shut_down(&self) {
+        for (game_id, handle) in self.get_handles() {
+            let transcript_game_id = game_id.clone();
+            let result = handle
+                .run(move |game| {
+                    publish_notice(&game.notification_channel, "ServerShutdown", None);
+                    // Checked before ending the game, since that logs an event of its own
+                    let got_going = game.got_going();
+                    game.end();
+                    got_going.then(|| game.make_transcript(&transcript_game_id))
                 })
-                .unwrap();
+                .await;
 
-            game.is_ended
-        };
-
-        if is_ended {
-            self.remove_game(game_id)?;
+            match result {
+                Ok(Some(transcript)) => {
+                    if let Err(err) = ARCHIVE_MANAGER.save(&transcript).await {
+                        warn!("Failed to archive game {:?}: {:?}", game_id, err);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => warn!("Could not shut down game {:?}: {:?}", game_id, err),
+            }
+            self.remove_game(&game_id);
         }
-
-        Ok(())
     }
-}
 
-#[tokio::main]
-async fn main() {
-    env_logger::init();
-
-    let start = chrono::Utc::now();
-    let jeopardy_data = data::load(DATABASE_PATH);
-    let time_taken = chrono::Utc::now() - start;
+    /// Broadcasts what changed in the given game since the last update, along with any new events
+    /// from its log. If that game is over, archive it and remove it from the map.
+    pub async fn broadcast_game_state_update(&self, game_id: &GameId) -> Result<(), Error> {
+        info!("broadcast_game_state_update: {:?}", game_id);
 
-    info!(
-        "Loaded {} categories and {} final jeopardy questions in {} ms (min year: {}, max year: {})",
-        jeopardy_data.categories.len(),
-        jeopardy_data.final_jeopardy_questions.len(),
-        time_taken.num_milliseconds(),
-        jeopardy_data.min_year,
-        jeopardy_data.max_year,
-    );
-    JEOPARDY_DATA.set(jeopardy_data).unwrap();
+        let transcript_game_id = game_id.clone();
+        let transcript = self
+            .with_game(game_id, move |game| {
+                let updates = game.make_updates();
+
+                let events = game.take_unpublished_events();
+                if !events.is_empty() {
+                    let mut kwargs = WampKwArgs::new();
+                    kwargs.insert(
+                        "events".to_string(),
+                        WampPayloadValue::Array(
+                            events
+                                .iter()
+                                .map(|event| WampPayloadValue::Object(event.serialize()))
+                                .collect(),
+                        ),
+                    );
+
+                    record_broadcast_size(&kwargs);
+                    MSG_QUEUE
+                        .get()
+                        .unwrap()
+                        .send(Message {
+                            topic: Cow::Owned(game.event_channel.clone()),
+                            args: None,
+                            kwargs: Some(kwargs),
+                        })
+                        .unwrap();
+                }
 
-    // Create our MPSC pair
-    let (sender, mut receiver) = mpsc::unbounded_channel();
-    MSG_QUEUE.set(sender).unwrap();
+                for (channel, update) in updates {
+                    record_broadcast_size(&update);
+                    MSG_QUEUE
+                        .get()
+                        .unwrap()
+                        .send(Message {
+                            topic: Cow::Owned(channel),
+                            args: None,
+                            kwargs: Some(update),
+                        })
+                        .unwrap();
+                }
 
-    let port: u16 = env::var(ROUTER_PORT_ENV_NAME)
-        .expect("Missing router port")
-        .parse()
-        .expect("Router port not integer");
-
-    info!("Connecting to WAMP server on port {}", port);
-    let (mut client, (event_loop, rpc_queue)) = wamp_async::Client::connect(
-        format!("ws://127.0.0.1:{}/ws", port),
-        Some(
-            wamp_async::ClientConfig::default()
-                .set_serializers(vec![wamp_async::SerializerType::Json]),
-        ),
-    )
-    .await
-    .expect("Failed to connect to router!");
-    info!("Connected!");
+                if game.is_ended {
+                    Some((
+                        game.make_transcript(&transcript_game_id),
+                        game.get_profile_results(),
+                    ))
+                } else {
+                    None
+                }
+            })
+            .await?;
 
-    // Spawn the WAMP event loop (which enables processing messages out-of-order).
-    tokio::spawn(event_loop);
+        if let Some((transcript, results)) = transcript {
+            self.remove_game(game_id);
 
-    // Spawn each new RPC call on its own task.
-    tokio::spawn(async move {
-        let mut rpc_queue = rpc_queue.expect("Missing RPC event queue!");
-        while let Some(rpc_event) = rpc_queue.recv().await {
-            tokio::spawn(rpc_event);
+            if let Err(err) = ARCHIVE_MANAGER.save(&transcript).await {
+                warn!("Failed to archive game {:?}: {:?}", game_id, err);
+            }
+            if let Err(err) = PROFILE_MANAGER.lock().await.record_game(&results).await {
+                warn!(
+                    "Failed to record profile stats for {:?}: {:?}",
+                    game_id, err
+                );
+            }
         }
-    });
-
-    info!("Event loop and RPC queue ready!");
-
-    // Spawn the garbage collection task (it removes games started more than 24 hours ago).
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(GC_INTERVAL).await;
-
-            info!("GC running...");
-            let now = Utc::now();
 
-            match STATE.games.try_write_for(OPERATION_TIMEOUT) {
-                Some(mut lock) => {
-                    trace!("GC got lock");
-                    let old_count = lock.len();
+        Ok(())
+    }
+}
 
-                    lock.retain(|_, ref mut v| {
-                        let time_since_started =
-                            now.signed_duration_since(v.get_mut().time_started);
-                        time_since_started
-                            < chrono::Duration::from_std(GC_CLEANUP_THRESHOLD).unwrap()
-                    });
+/// Publishes an event from the lobby index on the lobby channel.
+fn publish_lobby_event(event: WampKwArgs) {
+    MSG_QUEUE
+        .get()
+        .unwrap()
+        .send(Message {
+            topic: GAME_LOBBY_CHANNEL.into(),
+            args: None,
+            kwargs: Some(event),
+        })
+        .unwrap();
+}
 
-                    info!("GC done; removed {} games.", old_count - lock.len());
-                }
-                None => {
-                    warn!("GC could not run! Lock acquire timed out.");
-                }
-            }
-        }
-    });
+/// Publishes a one-off notice (like the game expiring) on a game's notification channel.
+fn publish_notice(channel: &str, notice_type: &str, expires_at: Option<DateTime<Utc>>) {
+    let mut notice = wamp_dict! {
+        "type" => notice_type.to_string(),
+    };
+    if let Some(expires_at) = expires_at {
+        notice.insert(
+            "expires_at".into(),
+            WampPayloadValue::String(expires_at.to_rfc3339()),
+        );
+    }
 
-    client
-        .join_realm(WAMP_REALM)
-        .await
-        .expect("Failed to join realm!");
-    info!("Joined realm {}!", WAMP_REALM);
+    MSG_QUEUE
+        .get()
+        .unwrap()
+        .send(Message {
+            topic: channel.to_string().into(),
+            args: None,
+            kwargs: Some(notice),
+        })
+        .unwrap();
+}
 
+/// Registers every RPC with the router. Called again after each reconnect.
+async fn register_rpcs(client: &wamp_async::Client<'static>) -> Result<(), wamp_async::WampError> {
     rpc_register!(client, {
         // Meta functions
         "jpdy.new_game" => server::make_game,
         "jpdy.join" => server::join_game,
         "jpdy.leave" => server::leave_game,
+        "jpdy.spectate" => server::spectate,
+        "jpdy.stop_spectating" => server::stop_spectating,
+        "jpdy.keep_spectating" => server::keep_spectating,
         "jpdy.list_games" => server::get_games,
         "jpdy.game_state" => server::get_game_state,
+        "jpdy.review" => server::review_game,
+        "jpdy.profile.register" => server::register_profile,
+        "jpdy.leaderboard" => server::get_leaderboard,
+        "jpdy.resolve_invite" => server::resolve_invite,
 
         // Moderator-only functions
         "jpdy.end_game" => server::end_game,
+        "jpdy.set_privacy" => server::set_privacy,
+        "jpdy.set_join_rules" => server::set_join_rules,
+        "jpdy.approve_join" => server::approve_join,
+        "jpdy.kick" => server::kick,
         "jpdy.new_board" => server::new_board,
         "jpdy.select_square" => server::select_square,
         "jpdy.enable_buzzer" => server::enable_buzzer,
+        "jpdy.reveal_daily_double_clue" => server::reveal_daily_double_clue,
         "jpdy.answer" => server::answer,
         "jpdy.final_jeopardy.start" => server::start_final_jeopardy,
         "jpdy.final_jeopardy.reveal_question" => server::reveal_final_jeopardy_question,
         "jpdy.final_jeopardy.lock_answers" => server::lock_final_jeopardy_answers,
         "jpdy.final_jeopardy.reveal_info" => server::reveal_final_jeopardy_info,
+        "jpdy.final_jeopardy.reveal_next" => server::reveal_next_final_jeopardy_info,
         "jpdy.final_jeopardy.evaluate_answer" => server::evaluate_final_jeopardy_answer,
         "jpdy.change_square_state" => server::change_square_state,
         "jpdy.change_player_score" => server::change_player_score,
+        "jpdy.event_log" => server::get_event_log,
+        "jpdy.undo" => server::undo,
+        "jpdy.redo" => server::redo,
 
         // Player-only functions
         "jpdy.submit_wager" => server::submit_wager,
         "jpdy.buzz" => server::buzz,
         "jpdy.submit_final_jeopardy_answer" => server::submit_final_jeopardy_answer,
+
+        // Admin functions (need the admin token)
+        "jpdy.admin.list_games" => admin::list_games,
+        "jpdy.admin.end_game" => admin::end_game,
+        "jpdy.admin.kick" => admin::kick,
+        "jpdy.admin.reload_data" => admin::reload_data,
+        "jpdy.admin.purge_avatars" => admin::purge_avatars,
+        "jpdy.admin.stats" => admin::get_stats,
     })
     .await
     .into_iter()
-    .collect::<Result<Vec<_>, _>>()
-    .expect("Failed to register RPCs!");
-    info!("RPCs registered!");
-
-    while let Some(msg) = receiver.recv().await {
-        client
-            .publish(msg.topic, msg.args, msg.kwargs, false)
-            .await
-            .expect("Failed to send message");
+    .collect::<Result<Vec<_>, _>>()?;
+    Ok(())
+}
+
+/// Records how big a message for a game's clients is once serialized.
+fn record_broadcast_size(kwargs: &WampKwArgs) {
+    if let Ok(bytes) = serde_json::to_vec(kwargs) {
+        METRICS.record_broadcast_size(bytes.len());
+    }
+}
+
+/// Waits for Ctrl-C or SIGTERM.
+async fn wait_for_shutdown_signal() {
+    let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())
+        .expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = signal::ctrl_c() => info!("Got Ctrl-C"),
+        _ = terminate.recv() => info!("Got SIGTERM"),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    lazy_static::initialize(&STARTED_AT);
+
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, config::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", config::USAGE);
+        return;
+    }
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Invalid config: {}", err);
+            std::process::exit(2);
+        }
+    };
+    info!("Config: {:?}", config);
+    CONFIG.set(config).unwrap();
+    let config = CONFIG.get().unwrap();
+
+    let start = chrono::Utc::now();
+    let jeopardy_data = data::load(&config.paths.database);
+    let time_taken = chrono::Utc::now() - start;
+    METRICS.set_dataset_load_time(time_taken.to_std().unwrap_or_default());
+
+    info!(
+        "Loaded {} categories and {} final jeopardy questions in {} ms (min year: {}, max year: {})",
+        jeopardy_data.categories.len(),
+        jeopardy_data.final_jeopardy_questions.len(),
+        time_taken.num_milliseconds(),
+        jeopardy_data.min_year,
+        jeopardy_data.max_year,
+    );
+    *JEOPARDY_DATA.write() = Arc::new(jeopardy_data);
+    lazy_static::initialize(&PROFILE_MANAGER);
+
+    // Create our MPSC pair
+    let (sender, receiver) = mpsc::unbounded_channel();
+    MSG_QUEUE.set(sender).unwrap();
+
+    // Spawn the garbage collection task (it removes games nobody has touched in a while).
+    tokio::spawn(gc::run(config.gc.clone()));
+
+    if config.metrics.enabled {
+        tokio::spawn(metrics::serve(config.metrics.address));
+    }
+
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let connection = tokio::spawn(connection::run(receiver, shutdown_receiver));
+
+    wait_for_shutdown_signal().await;
+    info!("Shutting down...");
+    STATE.shut_down().await;
+
+    // Profiles are saved as they change, so this just waits out any save still in progress.
+    drop(PROFILE_MANAGER.lock().await);
+
+    let _ = shutdown_sender.send(true);
+    if let Err(err) = connection.await {
+        warn!("Connection task failed: {:?}", err);
     }
+    info!("Bye!");
 }
@@ -0,0 +1,386 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    future::Future,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::*;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+};
+use wamp_async::{WampArgs, WampError, WampKwArgs};
+
+use crate::{CONFIG, METRICS};
+
+/// Where to serve metrics. It's meant for a scraper on the same machine or network, so it only
+/// listens on localhost unless told otherwise.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub address: SocketAddr,
+}
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            address: SocketAddr::from(([127, 0, 0, 1], 9898)),
+        }
+    }
+}
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+const SIZE_BUCKETS: &[f64] = &[
+    256.0,
+    1024.0,
+    4096.0,
+    16384.0,
+    65536.0,
+    262_144.0,
+    1_048_576.0,
+];
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+// A scraper only needs one connection at a time, so this just keeps idle or slow clients from
+// piling up.
+const MAX_CONNECTIONS: usize = 16;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>, // one per bucket, not cumulative
+    sum: f64,
+    count: u64,
+}
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Histogram {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = self.buckets.iter().position(|bound| value <= *bound) {
+            self.counts[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in self.buckets.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, self.count
+        );
+
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Counters and histograms about the running server, rendered in the Prometheus text format.
+pub struct Metrics {
+    rpc_calls: Mutex<BTreeMap<(&'static str, String), u64>>, // by procedure and result code
+    rpc_durations: Mutex<BTreeMap<&'static str, Histogram>>,
+    command_wait: Mutex<Histogram>,
+    broadcast_sizes: Mutex<Histogram>,
+    active_games: AtomicI64,
+    active_players: AtomicI64,
+    dataset_load_millis: AtomicU64,
+}
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            rpc_calls: Mutex::new(BTreeMap::new()),
+            rpc_durations: Mutex::new(BTreeMap::new()),
+            command_wait: Mutex::new(Histogram::new(DURATION_BUCKETS)),
+            broadcast_sizes: Mutex::new(Histogram::new(SIZE_BUCKETS)),
+            active_games: AtomicI64::new(0),
+            active_players: AtomicI64::new(0),
+            dataset_load_millis: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one finished RPC call. `code` is "ok", or the error code the caller got back.
+    pub fn record_rpc(&self, procedure: &'static str, code: String, duration: Duration) {
+        *self.rpc_calls.lock().entry((procedure, code)).or_insert(0) += 1;
+        self.rpc_durations
+            .lock()
+            .entry(procedure)
+            .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Records how long a command sat in a game's queue before the game got to it.
+    pub fn record_command_wait(&self, wait: Duration) {
+        self.command_wait.lock().observe(wait.as_secs_f64());
+    }
+
+    pub fn record_broadcast_size(&self, bytes: usize) {
+        self.broadcast_sizes.lock().observe(bytes as f64);
+    }
+
+    pub fn add_games(&self, delta: i64) {
+        self.active_games.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn add_players(&self, delta: i64) {
+        self.active_players.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn set_dataset_load_time(&self, duration: Duration) {
+        self.dataset_load_millis
+            .store(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Renders everything, plus the size of the avatar directory (which is measured when asked).
+    pub fn render(&self, avatar_bytes: u64) -> String {
+        let mut out = String::new();
+
+        write_header(
+            &mut out,
+            "jpdy_rpc_calls_total",
+            "counter",
+            "RPC calls by procedure and result code.",
+        );
+        for ((procedure, code), count) in self.rpc_calls.lock().iter() {
+            let _ = writeln!(
+                out,
+                "jpdy_rpc_calls_total{{procedure=\"{}\",code=\"{}\"}} {}",
+                procedure, code, count
+            );
+        }
+
+        write_header(
+            &mut out,
+            "jpdy_rpc_duration_seconds",
+            "histogram",
+            "How long RPC calls took, by procedure.",
+        );
+        for (procedure, histogram) in self.rpc_durations.lock().iter() {
+            histogram.render(
+                &mut out,
+                "jpdy_rpc_duration_seconds",
+                &format!("procedure=\"{}\"", procedure),
+            );
+        }
+
+        write_header(
+            &mut out,
+            "jpdy_game_command_wait_seconds",
+            "histogram",
+            "How long commands waited in a game's queue.",
+        );
+        self.command_wait
+            .lock()
+            .render(&mut out, "jpdy_game_command_wait_seconds", "");
+
+        write_header(
+            &mut out,
+            "jpdy_broadcast_size_bytes",
+            "histogram",
+            "Size of each update and event batch published for a game.",
+        );
+        self.broadcast_sizes
+            .lock()
+            .render(&mut out, "jpdy_broadcast_size_bytes", "");
+
+        let gauges = [
+            (
+                "jpdy_active_games",
+                "Games currently running.",
+                self.active_games.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "jpdy_active_players",
+                "Players seated in running games.",
+                self.active_players.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "jpdy_dataset_load_seconds",
+                "How long loading the clue dataset took.",
+                (self.dataset_load_millis.load(Ordering::Relaxed) as f64 / 1000.0).to_string(),
+            ),
+            (
+                "jpdy_avatar_storage_bytes",
+                "Total size of saved avatars.",
+                avatar_bytes.to_string(),
+            ),
+        ];
+        for (name, help, value) in gauges.iter() {
+            write_header(&mut out, name, "gauge", help);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        out
+    }
+}
+
+/// Runs an RPC handler, recording how long it took and how it went.
+pub async fn instrument<F>(
+    procedure: &'static str,
+    call: F,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError>
+where
+    F: Future<Output = Result<(Option<WampArgs>, Option<WampKwArgs>), WampError>>,
+{
+    let start = Instant::now();
+    let result = call.await;
+
+    let code = match &result {
+        Ok(_) => "ok".to_string(),
+        Err(WampError::UnknownError(code)) => code.clone(),
+        Err(_) => "wamp_error".to_string(),
+    };
+    METRICS.record_rpc(procedure, code, start.elapsed());
+
+    result
+}
+
+async fn directory_size(path: &Path) -> u64 {
+    let mut total = 0;
+    if let Ok(mut entries) = tokio::fs::read_dir(path).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(metadata) = entry.metadata().await {
+                if metadata.is_file() {
+                    total += metadata.len();
+                }
+            }
+        }
+    }
+    total
+}
+
+async fn handle_request(mut stream: TcpStream) -> std::io::Result<()> {
+    // Only the request line matters, but read the whole head so the client doesn't see a reset.
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let response = if request.starts_with("GET /metrics ") {
+        let avatars = directory_size(&CONFIG.get().unwrap().paths.avatars).await;
+        let body = METRICS.render(avatars);
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serves `GET /metrics` on the given address until the server stops. Each connection gets
+/// `REQUEST_TIMEOUT` to finish, and at most `MAX_CONNECTIONS` are handled at once.
+pub async fn serve(address: SocketAddr) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Couldn't serve metrics on {}: {}", address, err);
+            return;
+        }
+    };
+    info!("Serving metrics on http://{}/metrics", address);
+
+    // Waiting for a permit before accepting leaves extra connections queued in the backlog.
+    let permits = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        let permit = permits.clone().acquire_owned().await.unwrap();
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    match tokio::time::timeout(REQUEST_TIMEOUT, handle_request(stream)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => debug!("Metrics request failed: {}", err),
+                        Err(_) => debug!("Metrics request timed out"),
+                    }
+                    drop(permit);
+                });
+            }
+            Err(err) => warn!("Failed to accept metrics connection: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use std::time::Duration;
+
+    use super::Metrics;
+
+    #[test]
+    fn renders_counters_and_histograms() {
+        let metrics = Metrics::new();
+        metrics.record_rpc("jpdy.buzz", "ok".into(), Duration::from_millis(3));
+        metrics.record_rpc("jpdy.buzz", "ok".into(), Duration::from_millis(30));
+        metrics.record_rpc(
+            "jpdy.buzz",
+            "jpdy.locked_out".into(),
+            Duration::from_millis(3),
+        );
+        metrics.add_games(2);
+        metrics.add_games(-1);
+
+        let text = metrics.render(2048);
+        assert!(text.contains("jpdy_rpc_calls_total{procedure=\"jpdy.buzz\",code=\"ok\"} 2\n"));
+        assert!(text.contains(
+            "jpdy_rpc_calls_total{procedure=\"jpdy.buzz\",code=\"jpdy.locked_out\"} 1\n"
+        ));
+        assert!(text.contains(
+            "jpdy_rpc_duration_seconds_bucket{procedure=\"jpdy.buzz\",le=\"0.005\"} 2\n"
+        ));
+        assert!(text
+            .contains("jpdy_rpc_duration_seconds_bucket{procedure=\"jpdy.buzz\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("jpdy_rpc_duration_seconds_count{procedure=\"jpdy.buzz\"} 3\n"));
+        assert!(text.contains("jpdy_game_command_wait_seconds_count 0\n"));
+        assert!(text.contains("jpdy_active_games 1\n"));
+        assert!(text.contains("jpdy_avatar_storage_bytes 2048\n"));
+    }
+}
@@ -0,0 +1,114 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::errors::Error;
+
+// Measured in characters (after normalization), not bytes.
+const MAX_NAME_LENGTH: usize = 32;
+
+// Format characters (general category Cf) and other code points that render as nothing. Names
+// made with them look identical to other names, or reorder the text around them.
+const INVISIBLE_RANGES: &[(char, char)] = &[
+    ('\u{ad}', '\u{ad}'),
+    ('\u{34f}', '\u{34f}'),
+    ('\u{600}', '\u{605}'),
+    ('\u{61c}', '\u{61c}'),
+    ('\u{6dd}', '\u{6dd}'),
+    ('\u{70f}', '\u{70f}'),
+    ('\u{890}', '\u{891}'),
+    ('\u{8e2}', '\u{8e2}'),
+    ('\u{115f}', '\u{1160}'),
+    ('\u{17b4}', '\u{17b5}'),
+    ('\u{180b}', '\u{180f}'),
+    ('\u{200b}', '\u{200f}'),
+    ('\u{202a}', '\u{202e}'),
+    ('\u{2060}', '\u{206f}'),
+    ('\u{3164}', '\u{3164}'),
+    ('\u{feff}', '\u{feff}'),
+    ('\u{ffa0}', '\u{ffa0}'),
+    ('\u{fff0}', '\u{fffb}'),
+    ('\u{110bd}', '\u{110bd}'),
+    ('\u{110cd}', '\u{110cd}'),
+    ('\u{13430}', '\u{1343f}'),
+    ('\u{1bca0}', '\u{1bca3}'),
+    ('\u{1d173}', '\u{1d17a}'),
+    ('\u{e0000}', '\u{e0fff}'),
+];
+
+fn is_invisible(c: char) -> bool {
+    INVISIBLE_RANGES
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&c))
+}
+
+// Variation selectors only pick how the character before them is drawn (like emoji versus text
+// style), so they're dropped rather than rejected.
+fn is_variation_selector(c: char) -> bool {
+    ('\u{fe00}'..='\u{fe0f}').contains(&c) || ('\u{e0100}'..='\u{e01ef}').contains(&c)
+}
+
+/// Cleans up a player-supplied name: drops variation selectors, normalizes it to NFC, trims it,
+/// and collapses runs of whitespace. Fails if the result is empty, too long, or contains control
+/// or invisible formatting characters.
+pub fn normalize_name(name: &str) -> Result<String, Error> {
+    let name: String = name
+        .chars()
+        .filter(|c| !is_variation_selector(*c))
+        .nfc()
+        .collect();
+    if name.chars().any(|c| c.is_control() || is_invisible(c)) {
+        return Err(Error::InvalidName);
+    }
+
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    let length = name.chars().count();
+    if length == 0 || length > MAX_NAME_LENGTH {
+        return Err(Error::InvalidName);
+    }
+
+    Ok(name)
+}
+
+/// Gets the form of a (normalized) name used to check for duplicates, so that names which only
+/// differ by case or by compatibility characters (like full-width letters) count as the same.
+pub fn name_key(name: &str) -> String {
+    name.nfkc().flat_map(char::to_lowercase).collect()
+}
+
+#[cfg(test)]
+mod names_tests {
+    use super::{name_key, normalize_name};
+
+    #[test]
+    fn normalization() {
+        assert_eq!(normalize_name("  Alex   Trebek ").unwrap(), "Alex Trebek");
+        assert_eq!(normalize_name("Cafe\u{301}").unwrap(), "Caf\u{e9}");
+        assert!(normalize_name("").is_err());
+        assert!(normalize_name(" \t ").is_err());
+        assert!(normalize_name("bad\u{7}name").is_err());
+        assert!(normalize_name("bad\nname").is_err());
+        assert!(normalize_name(&"x".repeat(33)).is_err());
+        assert!(normalize_name(&"\u{e9}".repeat(32)).is_ok());
+    }
+
+    #[test]
+    fn invisible_characters() {
+        assert!(normalize_name("Ken\u{200b}").is_err());
+        assert!(normalize_name("K\u{200d}en").is_err());
+        assert!(normalize_name("\u{2060}Ken").is_err());
+        assert!(normalize_name("Ken\u{202e}nek").is_err());
+        assert!(normalize_name("\u{2066}Ken\u{2069}").is_err());
+        assert!(normalize_name("Ken\u{e0001}").is_err());
+        assert!(normalize_name("Ken\u{ad}").is_err());
+        assert_eq!(
+            normalize_name("Ken \u{2764}\u{fe0f}").unwrap(),
+            "Ken \u{2764}"
+        );
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(name_key("Ken"), name_key("KEN"));
+        assert_eq!(name_key("\u{ff2b}en"), name_key("Ken"));
+        assert_ne!(name_key("Ken"), name_key("Kent"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use wamp_async::{WampKwArgs, WampPayloadValue};
+
+use crate::{errors::Error, names};
+
+const MIN_SECRET_LENGTH: usize = 4;
+
+/// All-time totals for one profile, summed over every game it finished.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct CareerStats {
+    games_played: u32,
+    games_won: u32,
+    total_winnings: i64,
+    best_score: i64,
+    correct: u32,
+    incorrect: u32,
+    coryat: i64,
+}
+
+/// How a player with a profile did in one game.
+#[derive(Debug)]
+pub(crate) struct GameResult {
+    pub profile: String, // key of the profile, see `names::name_key`
+    pub score: i64,
+    pub won: bool,
+    pub correct: u32,
+    pub incorrect: u32,
+    pub coryat: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Profile {
+    name: String,
+    salt: String,
+    secret_hash: String,
+    avatar_url: Option<String>,
+    stats: CareerStats,
+}
+impl Profile {
+    fn hash_secret(salt: &str, secret: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(salt.as_bytes());
+        hasher.update(secret.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub(crate) fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn get_avatar_url(&self) -> Option<&str> {
+        self.avatar_url.as_deref()
+    }
+
+    pub(crate) fn serialize(&self) -> WampKwArgs {
+        let mut result = wamp_dict! {
+            "name" => self.name.clone(),
+            "total_winnings" => self.stats.total_winnings.to_string(),
+            "best_score" => self.stats.best_score.to_string(),
+            "coryat" => self.stats.coryat.to_string(),
+        };
+        result.insert(
+            "avatar_url".into(),
+            match self.avatar_url {
+                Some(ref avatar_url) => WampPayloadValue::String(avatar_url.clone()),
+                None => WampPayloadValue::Null,
+            },
+        );
+        for (key, count) in &[
+            ("games_played", self.stats.games_played),
+            ("games_won", self.stats.games_won),
+            ("correct", self.stats.correct),
+            ("incorrect", self.stats.incorrect),
+        ] {
+            result.insert((*key).into(), WampPayloadValue::Number((*count).into()));
+        }
+        result
+    }
+}
+
+/// Optional player accounts, stored as a single JSON file. Profiles are keyed by the player's
+/// name, so registering a name reserves it.
+pub(crate) struct ProfileManager {
+    path: PathBuf,
+    profiles: HashMap<String, Profile>,
+}
+impl ProfileManager {
+    pub(crate) fn load(path: PathBuf) -> Result<Self, Error> {
+        let profiles = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(ProfileManager { path, profiles })
+    }
+
+    async fn save(&self) -> Result<(), Error> {
+        // Write to a temporary file first so a crash can't leave a half-written store behind
+        let temp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec(&self.profiles)?).await?;
+        tokio::fs::rename(&temp_path, &self.path).await?;
+        Ok(())
+    }
+
+    pub(crate) fn get_profile_count(&self) -> usize {
+        self.profiles.len()
+    }
+
+    /// Every avatar saved to a profile.
+    pub(crate) fn get_avatar_urls(&self) -> impl Iterator<Item = &str> {
+        self.profiles.values().filter_map(Profile::get_avatar_url)
+    }
+
+    pub(crate) fn is_registered(&self, name: &str) -> bool {
+        self.profiles.contains_key(&names::name_key(name))
+    }
+
+    pub(crate) async fn register(
+        &mut self,
+        name: String,
+        secret: &str,
+        avatar_url: Option<String>,
+    ) -> Result<&Profile, Error> {
+        if secret.chars().count() < MIN_SECRET_LENGTH {
+            return Err(Error::BadArgument);
+        }
+
+        let key = names::name_key(&name);
+        if self.profiles.contains_key(&key) {
+            return Err(Error::ProfileExists);
+        }
+
+        let salt = Uuid::new_v4().to_string();
+        let secret_hash = Profile::hash_secret(&salt, secret);
+        self.profiles.insert(
+            key.clone(),
+            Profile {
+                name,
+                salt,
+                secret_hash,
+                avatar_url,
+                stats: Default::default(),
+            },
+        );
+        self.save().await?;
+
+        Ok(&self.profiles[&key])
+    }
+
+    /// Checks a profile's credentials, returning its key and the profile itself.
+    pub(crate) fn authenticate(
+        &self,
+        name: &str,
+        secret: &str,
+    ) -> Result<(String, &Profile), Error> {
+        let key = names::name_key(name);
+        match self.profiles.get(&key) {
+            Some(profile) if Profile::hash_secret(&profile.salt, secret) == profile.secret_hash => {
+                Ok((key, profile))
+            }
+            _ => Err(Error::BadCredentials),
+        }
+    }
+
+    pub(crate) async fn set_avatar(&mut self, key: &str, avatar_url: String) -> Result<(), Error> {
+        let profile = self.profiles.get_mut(key).ok_or(Error::BadCredentials)?;
+        if profile.avatar_url.as_ref() != Some(&avatar_url) {
+            profile.avatar_url = Some(avatar_url);
+            self.save().await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn record_game(&mut self, results: &[GameResult]) -> Result<(), Error> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        for result in results {
+            if let Some(profile) = self.profiles.get_mut(&result.profile) {
+                let stats = &mut profile.stats;
+                if stats.games_played == 0 || result.score > stats.best_score {
+                    stats.best_score = result.score;
+                }
+                stats.games_played += 1;
+                if result.won {
+                    stats.games_won += 1;
+                }
+                stats.total_winnings += result.score;
+                stats.correct += result.correct;
+                stats.incorrect += result.incorrect;
+                stats.coryat += result.coryat;
+            }
+        }
+
+        self.save().await
+    }
+
+    /// Profiles which have finished at least one game, most wins first (ties are broken by
+    /// total winnings).
+    pub(crate) fn leaderboard(&self, limit: usize) -> Vec<&Profile> {
+        let mut profiles: Vec<_> = self
+            .profiles
+            .values()
+            .filter(|profile| profile.stats.games_played > 0)
+            .collect();
+        profiles.sort_by(|a, b| {
+            b.stats
+                .games_won
+                .cmp(&a.stats.games_won)
+                .then(b.stats.total_winnings.cmp(&a.stats.total_winnings))
+        });
+        profiles.truncate(limit);
+        profiles
+    }
+}
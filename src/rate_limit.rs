@@ -0,0 +1,102 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::errors::Error;
+
+/// A token bucket rate limiter with one bucket per key. Each bucket holds up to `burst` tokens and
+/// gets `burst` new ones every `period`; every call takes one.
+pub struct RateLimiter<K> {
+    burst: f64,
+    tokens_per_second: f64,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(burst: u32, period: Duration) -> Self {
+        RateLimiter {
+            burst: burst.into(),
+            tokens_per_second: f64::from(burst) / period.as_secs_f64(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the key's bucket, or fails if there aren't any left.
+    pub fn check(&self, key: K) -> Result<(), Error> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: K, now: Instant) -> Result<(), Error> {
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            last_update: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_update);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.tokens_per_second).min(self.burst);
+        bucket.last_update = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Error::RateLimited);
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Forgets about buckets which would be full by now, since they behave just like new ones.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        let time_to_fill = Duration::from_secs_f64(self.burst / self.tokens_per_second);
+        self.buckets
+            .lock()
+            .retain(|_, bucket| now.saturating_duration_since(bucket.last_update) < time_to_fill);
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn bursts_and_refills() {
+        let limiter = RateLimiter::new(3, Duration::from_secs(3));
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at("a", start).is_ok());
+        }
+        assert!(limiter.check_at("a", start).is_err());
+        // Other keys have their own buckets.
+        assert!(limiter.check_at("b", start).is_ok());
+
+        // One token per second comes back.
+        assert!(limiter
+            .check_at("a", start + Duration::from_secs(1))
+            .is_ok());
+        assert!(limiter
+            .check_at("a", start + Duration::from_secs(1))
+            .is_err());
+
+        // Buckets never hold more than the burst size.
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check_at("a", later).is_ok());
+        }
+        assert!(limiter.check_at("a", later).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{convert::TryInto, fmt, str::FromStr};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct Seed {
     value: u32,
 }
@@ -5,40 +5,178 @@ use crate::{
     errors::Error,
     game::{
         board::{Location, SquareState},
-        AnswerType, FinalJeopardyInfoType, Player, PlayerType,
+        AnswerType, FinalJeopardyInfoType, Game, Player, PlayerType,
     },
+    lobby::LobbyQuery,
+    names,
     seed::Seed,
-    AuthToken, GameId, Message, PlayerId, AVATAR_MANAGER, GAME_LOBBY_CHANNEL, MSG_QUEUE,
-    OPERATION_TIMEOUT, STATE,
+    AuthToken, GameId, InviteCode, Message, PlayerId, ARCHIVE_MANAGER, AVATAR_MANAGER, CONFIG,
+    GAME_LIMITER, INVITE_LIMITER, JEOPARDY_DATA, JOIN_LIMITER, LOBBY, MSG_QUEUE, NEW_GAME_LIMITER,
+    PLAYER_LIMITER, PROFILE_LIMITER, PROFILE_MANAGER, STATE,
 };
 
+const DEFAULT_LEADERBOARD_SIZE: usize = 20;
+const MAX_LEADERBOARD_SIZE: usize = 100;
+const DEFAULT_LOBBY_PAGE_SIZE: usize = 50;
+const MAX_LOBBY_PAGE_SIZE: usize = 100;
+
 fn get_str_parse<T: std::str::FromStr>(arg: &WampPayloadValue) -> Result<T, Error> {
     let string = get_str(arg)?;
     string.parse().map_err(|_| Error::BadArgument)
 }
 
-fn get_str(arg: &WampPayloadValue) -> Result<&str, Error> {
+pub(crate) fn get_opt_str_parse<T: std::str::FromStr>(
+    arg: Option<&WampPayloadValue>,
+) -> Result<Option<T>, Error> {
+    arg.map(get_str_parse).transpose()
+}
+
+pub(crate) fn get_str(arg: &WampPayloadValue) -> Result<&str, Error> {
     match arg {
         WampPayloadValue::String(ref string) => Ok(string),
         _ => Err(Error::BadArgument),
     }
 }
 
-fn get_uuid(arg: &WampPayloadValue) -> Result<uuid::Uuid, Error> {
+pub(crate) fn get_uuid(arg: &WampPayloadValue) -> Result<uuid::Uuid, Error> {
     let string = get_str(arg)?;
     uuid::Uuid::parse_str(string).map_err(|_| Error::BadArgument)
 }
 
+/// Gets the arguments every in-game call has.
 fn get_common_args(
     kwargs: &wamp_async::WampKwArgs,
 ) -> Result<(GameId, PlayerId, AuthToken), Error> {
-    Ok((
-        GameId(get_uuid(kwargs.get("game_id").ok_or(Error::BadArgument)?)?),
-        PlayerId(get_uuid(
-            kwargs.get("player_id").ok_or(Error::BadArgument)?,
-        )?),
-        AuthToken(get_uuid(kwargs.get("auth").ok_or(Error::BadArgument)?)?),
-    ))
+    let game_id = GameId(get_uuid(kwargs.get("game_id").ok_or(Error::BadArgument)?)?);
+    let player_id = PlayerId(get_uuid(
+        kwargs.get("player_id").ok_or(Error::BadArgument)?,
+    )?);
+    let auth = AuthToken(get_uuid(kwargs.get("auth").ok_or(Error::BadArgument)?)?);
+
+    Ok((game_id, player_id, auth))
+}
+
+/// Works out who the caller is in the game, and charges the call against their and the game's
+/// rate limits. Calls that don't authenticate aren't charged to anyone.
+fn authenticate(
+    game: &Game,
+    player_id: &PlayerId,
+    auth: &AuthToken,
+) -> Result<Option<PlayerType>, Error> {
+    let player_type = game.auth_and_get_player_type(player_id, auth);
+    if player_type.is_some() {
+        PLAYER_LIMITER.check(player_id.clone())?;
+        GAME_LIMITER.check(game.id.clone())?;
+    }
+    Ok(player_type)
+}
+
+/// Reads the optional version of the game the caller is acting on.
+fn get_expected_version(kwargs: &wamp_async::WampKwArgs) -> Result<Option<u64>, Error> {
+    get_opt_str_parse(kwargs.get("expected_version"))
+}
+
+/// Finds the game an invite code or game ID refers to; invite codes take precedence.
+fn get_game_id(kwargs: &wamp_async::WampKwArgs) -> Result<GameId, Error> {
+    match (kwargs.get("invite_code"), kwargs.get("game_id")) {
+        (Some(invite_code), _) => {
+            let invite_code: InviteCode = get_str_parse(invite_code)?;
+            STATE.resolve_invite_code(&invite_code)
+        }
+        (None, Some(game_id)) => Ok(GameId(get_uuid(game_id)?)),
+        (None, None) => Err(Error::BadArgument),
+    }
+}
+
+/// Whether the caller gave this game's invite code (and not some other game's).
+fn is_invited(kwargs: &wamp_async::WampKwArgs, game_id: &GameId) -> Result<bool, Error> {
+    match kwargs.get("invite_code") {
+        Some(invite_code) => {
+            Ok(STATE.resolve_invite_code(&get_str_parse(invite_code)?)? == *game_id)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Checks that someone who isn't in the game yet may join or watch it: only an invite code gets
+/// you into a private game, and the password has to match if there is one.
+fn check_entry(game: &Game, invited: bool, password: Option<&str>) -> Result<(), Error> {
+    if game.is_private && !invited {
+        return Err(Error::NotAllowed);
+    }
+    if !game.check_password(password) {
+        return Err(Error::WrongPassword);
+    }
+    Ok(())
+}
+
+/// Reads the optional player cap. Zero (or leaving it out) means there is no cap.
+fn get_max_players(kwargs: &wamp_async::WampKwArgs) -> Result<Option<usize>, Error> {
+    Ok(get_opt_str_parse(kwargs.get("max_players"))?.filter(|max| *max > 0))
+}
+
+// The range of air years to pick clues from, which has to have something in it.
+fn get_year_range(kwargs: &wamp_async::WampKwArgs) -> Result<(u16, u16), Error> {
+    let min_year = get_str_parse(kwargs.get("min_year").ok_or(Error::BadArgument)?)?;
+    let max_year = get_str_parse(kwargs.get("max_year").ok_or(Error::BadArgument)?)?;
+    if min_year > max_year {
+        return Err(Error::BadArgument);
+    }
+    Ok((min_year, max_year))
+}
+
+/// Works out who is joining: either someone signed in with a profile (whose name and saved avatar
+/// are used, unless they upload a new avatar), or an anonymous player with a name and avatar.
+/// Returns the name, avatar URL and profile key. A new avatar isn't saved to the profile until
+/// `remember_avatar` is called.
+async fn get_identity(
+    kwargs: &wamp_async::WampKwArgs,
+) -> Result<(String, String, Option<String>), Error> {
+    let avatar_url = match kwargs.get("avatar") {
+        Some(avatar) => Some(
+            AVATAR_MANAGER
+                .lock()
+                .await
+                .save_avatar(get_str(avatar)?)
+                .await?,
+        ),
+        None => None,
+    };
+
+    let profiles = PROFILE_MANAGER.lock().await;
+    match kwargs.get("profile_name") {
+        Some(profile_name) => {
+            let secret = get_str(kwargs.get("profile_secret").ok_or(Error::BadArgument)?)?;
+            let (key, profile) = profiles.authenticate(get_str(profile_name)?, secret)?;
+            let name = profile.get_name().to_string();
+            let saved_avatar_url = profile.get_avatar_url().map(str::to_string);
+
+            let avatar_url = avatar_url.or(saved_avatar_url).ok_or(Error::BadArgument)?;
+            Ok((name, avatar_url, Some(key)))
+        }
+        None => {
+            let name = names::normalize_name(get_str(
+                kwargs.get("player_name").ok_or(Error::BadArgument)?,
+            )?)?;
+
+            // Registered names belong to their profile
+            if profiles.is_registered(&name) {
+                return Err(Error::NameTaken);
+            }
+            Ok((name, avatar_url.ok_or(Error::BadArgument)?, None))
+        }
+    }
+}
+
+/// Makes the avatar someone signed in with their profile's saved one. This waits until they've
+/// made it into the game, so that a join which fails doesn't change their profile.
+async fn remember_avatar(profile: Option<String>, avatar_url: String) {
+    if let Some(key) = profile {
+        let mut profiles = PROFILE_MANAGER.lock().await;
+        if let Err(err) = profiles.set_avatar(&key, avatar_url).await {
+            warn!("Failed to save the avatar for profile {}: {:?}", key, err);
+        }
+    }
 }
 
 /// Create a new game and add it to the state.
@@ -49,15 +187,26 @@ pub async fn make_game(
     info!("make_game");
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
-    let player_name = get_str(kwargs.get("player_name").ok_or(Error::BadArgument)?)?;
-    let avatar_url = AVATAR_MANAGER
-        .lock()
-        .await
-        .save_avatar(get_str(kwargs.get("avatar").ok_or(Error::BadArgument)?)?)
-        .await?;
+    let defaults = &CONFIG.get().unwrap().game_defaults;
+    let is_private: bool = get_opt_str_parse(kwargs.get("private"))?.unwrap_or(defaults.private);
+    let password = kwargs.get("password").map(get_str).transpose()?;
+    let max_players = match kwargs.get("max_players") {
+        Some(_) => get_max_players(&kwargs)?,
+        None => defaults.max_players,
+    };
+    let approve_joins: bool =
+        get_opt_str_parse(kwargs.get("approve_joins"))?.unwrap_or(defaults.approve_joins);
+
+    NEW_GAME_LIMITER.check(())?;
+    let (player_name, avatar_url, profile) = get_identity(&kwargs).await?;
 
-    let (game_id, player_id, auth_token, moderator_channel) =
-        STATE.add_game(player_name.to_string(), avatar_url)?;
+    let (game_id, player_id, auth_token, moderator_channel, notification_channel, invite_code) =
+        STATE.add_game(player_name.clone(), avatar_url.clone(), |game| {
+            game.set_private(is_private);
+            game.set_password(password);
+            game.set_join_rules(max_players, approve_joins);
+        })?;
+    remember_avatar(profile, avatar_url).await;
 
     trace!(
         "Creating game {} with moderator named {} (assigned mod channel: {:?})",
@@ -66,16 +215,6 @@ pub async fn make_game(
         moderator_channel
     );
 
-    MSG_QUEUE
-        .get()
-        .unwrap()
-        .send(Message {
-            topic: GAME_LOBBY_CHANNEL.into(),
-            args: None,
-            kwargs: Some(STATE.get_games()?),
-        })
-        .unwrap();
-
     Ok((
         None,
         Some(wamp_dict! {
@@ -83,11 +222,14 @@ pub async fn make_game(
             "token" => auth_token.to_string(),        // The player's auth token
             "player_id" => player_id.to_string(),     // The player's ID
             "moderator_channel" => moderator_channel, // The channel for the moderator to subscribe to
+            "notification_channel" => notification_channel, // One-off notices, like the game expiring
+            "invite_code" => invite_code.to_string(), // A short code others can use to find the game
         }),
     ))
 }
 
-/// Join an existing game.
+/// Join an existing game, identified either by its ID or an invite code (which private games
+/// need).
 pub async fn join_game(
     _: Option<WampArgs>,
     kwargs: Option<WampKwArgs>,
@@ -95,43 +237,43 @@ pub async fn join_game(
     debug!("join_game");
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
-    let player_name = get_str(kwargs.get("player_name").ok_or(Error::BadArgument)?)?;
-    let game_id = GameId(get_uuid(kwargs.get("game_id").ok_or(Error::BadArgument)?)?);
-    let avatar_url = AVATAR_MANAGER
-        .lock()
-        .await
-        .save_avatar(get_str(kwargs.get("avatar").ok_or(Error::BadArgument)?)?)
-        .await?;
-
-    let (auth_token, player_id, player_channel) = {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let player = Player::new(player_name.to_string(), avatar_url);
-        let auth = player.get_auth();
-        let player_id = game.add_player(player);
-
-        (auth, player_id, game.player_state_channel.clone())
-    };
-
-    // Update the game lobby
-    MSG_QUEUE
-        .get()
-        .unwrap()
-        .send(Message {
-            topic: GAME_LOBBY_CHANNEL.into(),
-            args: None,
-            kwargs: Some(STATE.get_games()?),
-        })
-        .unwrap();
+    let game_id = get_game_id(&kwargs)?;
+    let invited = is_invited(&kwargs, &game_id)?;
+    let password = kwargs
+        .get("password")
+        .map(get_str)
+        .transpose()?
+        .map(str::to_string);
+
+    JOIN_LIMITER.check(game_id.clone())?;
+    let (player_name, avatar_url, profile) = get_identity(&kwargs).await?;
+    let (joined_profile, joined_avatar_url) = (profile.clone(), avatar_url.clone());
+
+    let (auth_token, player_id, player_channel, private_channel, notification_channel, status) =
+        STATE
+            .with_game(&game_id, move |game| -> Result<_, Error> {
+                check_entry(game, invited, password.as_deref())?;
+
+                let mut player = Player::new(player_name, avatar_url);
+                player.set_profile(profile);
+                if game.is_banned(&player) {
+                    return Err(Error::Banned);
+                }
+                let auth = player.get_auth();
+                let private_channel = player.get_channel().to_string();
+                let (player_id, status) = game.add_player(player)?;
+
+                Ok((
+                    auth,
+                    player_id,
+                    game.player_state_channel.clone(),
+                    private_channel,
+                    game.notification_channel.clone(),
+                    status,
+                ))
+            })
+            .await??;
+    remember_avatar(joined_profile, joined_avatar_url).await;
 
     // Update the players and moderator.
     STATE.broadcast_game_state_update(&game_id).await?;
@@ -139,9 +281,13 @@ pub async fn join_game(
     Ok((
         None,
         Some(wamp_dict! {
+            "game_id" => game_id.to_string(),
             "player_id" => player_id.to_string(),
             "token" => auth_token.to_string(),
             "channel" => player_channel,
+            "private_channel" => private_channel, // This player's own view of the game
+            "notification_channel" => notification_channel,
+            "status" => status.to_string(), // Whether the player got a seat or has to wait
         }),
     ))
 }
@@ -156,54 +302,335 @@ pub async fn leave_game(
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
     let target = PlayerId(get_uuid(kwargs.get("target").ok_or(Error::BadArgument)?)?);
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        match game.auth_and_get_player_type(&player_id, &auth) {
-            Some(PlayerType::Moderator) => {
-                game.remove_player(target);
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            match authenticate(game, &player_id, &auth)? {
+                Some(PlayerType::Moderator) => {
+                    game.remove_player(target);
+                }
+                Some(PlayerType::Player) | Some(PlayerType::Waiting) if player_id == target => {
+                    game.remove_player(target);
+                }
+                _ => return Err(Error::NotAllowed),
             }
-            Some(PlayerType::Player) if player_id == target => {
-                game.remove_player(target);
+
+            Ok(())
+        })
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Moderator only: remove a player from the game, optionally banning them from rejoining
+pub async fn kick(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("kick");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let target = PlayerId(get_uuid(kwargs.get("target").ok_or(Error::BadArgument)?)?);
+    let ban: bool = get_opt_str_parse(kwargs.get("ban"))?.unwrap_or(false);
+    let reason = kwargs.get("reason").map(get_str).transpose()?;
+
+    let kicked = target.clone();
+    let notification_channel = STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.kick_player(kicked, ban)?;
+            } else {
+                return Err(Error::NotAllowed);
             }
-            _ => return Err(Error::NotAllowed.into()),
-        }
-    }
 
-    // Update the game lobby
+            Ok(game.notification_channel.clone())
+        })
+        .await??;
+
+    publish_kick_notice(notification_channel, &target, ban, reason);
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Lets a kicked player know why they were removed.
+pub(crate) fn publish_kick_notice(
+    notification_channel: String,
+    target: &PlayerId,
+    ban: bool,
+    reason: Option<&str>,
+) {
+    let mut notice = wamp_dict! {
+        "type" => "Kicked".into(),
+        "player_id" => target.to_string(),
+    };
+    notice.insert("banned".into(), WampPayloadValue::Bool(ban));
+    if let Some(reason) = reason {
+        notice.insert("reason".into(), WampPayloadValue::String(reason.into()));
+    }
     MSG_QUEUE
         .get()
         .unwrap()
         .send(Message {
-            topic: GAME_LOBBY_CHANNEL.into(),
+            topic: notification_channel.into(),
             args: None,
-            kwargs: Some(STATE.get_games()?),
+            kwargs: Some(notice),
         })
         .unwrap();
-
-    STATE.broadcast_game_state_update(&game_id).await?;
-    Ok((None, None))
 }
 
-/// Get the list of open games.
+/// Get a page of the open games, optionally filtered by name, phase, whether they have a password
+/// or whether there's a seat free.
 pub async fn get_games(
     _: Option<WampArgs>,
-    _: Option<WampKwArgs>,
+    kwargs: Option<WampKwArgs>,
 ) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
     info!("get_games");
-    Ok((None, Some(STATE.get_games()?)))
+
+    let kwargs = kwargs.unwrap_or_default();
+    let query = LobbyQuery {
+        offset: get_opt_str_parse(kwargs.get("offset"))?.unwrap_or(0),
+        limit: get_opt_str_parse(kwargs.get("limit"))?
+            .unwrap_or(DEFAULT_LOBBY_PAGE_SIZE)
+            .min(MAX_LOBBY_PAGE_SIZE),
+        search: kwargs
+            .get("search")
+            .map(get_str)
+            .transpose()?
+            .filter(|search| !search.is_empty())
+            .map(str::to_string),
+        has_password: get_opt_str_parse(kwargs.get("has_password"))?,
+        phase: kwargs
+            .get("phase")
+            .map(get_str)
+            .transpose()?
+            .map(str::to_string),
+        joinable: get_opt_str_parse(kwargs.get("joinable"))?,
+    };
+
+    let mut result = LOBBY.list(&query);
+    let data = JEOPARDY_DATA.read().clone();
+    result.insert(
+        "min_year".to_string(),
+        WampPayloadValue::Number(data.min_year.into()),
+    );
+    result.insert(
+        "max_year".to_string(),
+        WampPayloadValue::Number(data.max_year.into()),
+    );
+
+    Ok((None, Some(result)))
+}
+
+/// Watch a game without playing in it, identified either by its ID or an invite code (which
+/// private games need).
+pub async fn spectate(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    debug!("spectate");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let game_id = get_game_id(&kwargs)?;
+    let invited = is_invited(&kwargs, &game_id)?;
+    let password = kwargs
+        .get("password")
+        .map(get_str)
+        .transpose()?
+        .map(str::to_string);
+
+    JOIN_LIMITER.check(game_id.clone())?;
+    let (spectator_id, channel, state) = STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            check_entry(game, invited, password.as_deref())?;
+
+            let spectator_id = game.add_spectator()?;
+            Ok((
+                spectator_id,
+                game.player_state_channel.clone(),
+                game.serialize(false),
+            ))
+        })
+        .await??;
+
+    let mut result = wamp_dict! {
+        "game_id" => game_id.to_string(),
+        "spectator_id" => spectator_id.to_string(),
+        "channel" => channel,
+    };
+    result.insert("state".into(), WampPayloadValue::Object(state));
+
+    Ok((None, Some(result)))
+}
+
+/// Stop watching a game.
+pub async fn stop_spectating(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    debug!("stop_spectating");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let game_id = get_game_id(&kwargs)?;
+    let spectator_id = PlayerId(get_uuid(
+        kwargs.get("spectator_id").ok_or(Error::BadArgument)?,
+    )?);
+
+    STATE
+        .with_game(&game_id, move |game| game.remove_spectator(&spectator_id))
+        .await?;
+
+    Ok((None, None))
+}
+
+/// Let the server know a spectator is still watching. Spectators who stop checking in are
+/// dropped by the garbage collector.
+pub async fn keep_spectating(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    debug!("keep_spectating");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let game_id = get_game_id(&kwargs)?;
+    let spectator_id = PlayerId(get_uuid(
+        kwargs.get("spectator_id").ok_or(Error::BadArgument)?,
+    )?);
+
+    let still_watching = STATE
+        .with_game(&game_id, move |game| game.refresh_spectator(&spectator_id))
+        .await?;
+    if !still_watching {
+        return Err(Error::NoSuchPlayer.into());
+    }
+
+    Ok((None, None))
 }
 
-/// Get the state for one game.
+/// Look up a game by its invite code, so that private games can be joined or spectated.
+pub async fn resolve_invite(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    debug!("resolve_invite");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let invite_code: InviteCode =
+        get_str_parse(kwargs.get("invite_code").ok_or(Error::BadArgument)?)?;
+
+    INVITE_LIMITER.check(())?;
+    let game_id = STATE.resolve_invite_code(&invite_code)?;
+
+    let result = STATE
+        .with_game(&game_id.clone(), move |game| {
+            let mut result = wamp_dict! {
+                "game_id" => game_id.to_string(),
+                "moderator" => game.get_moderator_name().into(),
+            };
+            result.insert(
+                "has_password".into(),
+                WampPayloadValue::Bool(game.has_password()),
+            );
+            result
+        })
+        .await?;
+
+    Ok((None, Some(result)))
+}
+
+/// Get the transcript of a finished game, and optionally what the game looked like at one step.
+/// Only those who were in the game can review it, so it needs the game's invite code.
+pub async fn review_game(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    debug!("review_game");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let game_id = GameId(get_uuid(kwargs.get("game_id").ok_or(Error::BadArgument)?)?);
+    let invite_code = get_str(kwargs.get("invite_code").ok_or(Error::BadArgument)?)?;
+    let step: Option<usize> = get_opt_str_parse(kwargs.get("step"))?;
+
+    JOIN_LIMITER.check(game_id.clone())?;
+    let transcript = ARCHIVE_MANAGER.load(&game_id).await?;
+    if !transcript.check_invite_code(invite_code) {
+        return Err(Error::NotAllowed.into());
+    }
+
+    let mut result = transcript.serialize_summary();
+    if let Some(step) = step {
+        let review = transcript.review(step).ok_or(Error::BadArgument)?;
+        result.insert("step".into(), WampPayloadValue::Object(review.serialize()));
+    }
+
+    Ok((None, Some(result)))
+}
+
+/// Create a profile, so that a player's name, avatar and stats carry over between games.
+pub async fn register_profile(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("register_profile");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let name = names::normalize_name(get_str(kwargs.get("name").ok_or(Error::BadArgument)?)?)?;
+    let secret = get_str(kwargs.get("secret").ok_or(Error::BadArgument)?)?;
+
+    PROFILE_LIMITER.check(())?;
+    let avatar_url = match kwargs.get("avatar") {
+        Some(avatar) => Some(
+            AVATAR_MANAGER
+                .lock()
+                .await
+                .save_avatar(get_str(avatar)?)
+                .await?,
+        ),
+        None => None,
+    };
+
+    let mut profiles = PROFILE_MANAGER.lock().await;
+    let profile = profiles.register(name, secret, avatar_url).await?;
+
+    Ok((None, Some(profile.serialize())))
+}
+
+/// Get the all-time leaderboard across every profile that has finished a game.
+pub async fn get_leaderboard(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    debug!("get_leaderboard");
+
+    let limit: usize = match kwargs {
+        Some(ref kwargs) => get_opt_str_parse(kwargs.get("limit"))?,
+        None => None,
+    }
+    .unwrap_or(DEFAULT_LEADERBOARD_SIZE)
+    .min(MAX_LEADERBOARD_SIZE);
+
+    let profiles = PROFILE_MANAGER.lock().await;
+    let mut result = WampKwArgs::new();
+    result.insert(
+        "leaderboard".into(),
+        WampPayloadValue::Array(
+            profiles
+                .leaderboard(limit)
+                .into_iter()
+                .map(|profile| WampPayloadValue::Object(profile.serialize()))
+                .collect(),
+        ),
+    );
+
+    Ok((None, Some(result)))
+}
+
+/// Get the state for one game. The moderator and players authenticate as usual; anyone else has
+/// to be spectating already, or give the game's password (and its invite code, if it's private).
 pub async fn get_game_state(
     _: Option<WampArgs>,
     kwargs: Option<WampKwArgs>,
@@ -212,22 +639,72 @@ pub async fn get_game_state(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let spectator_id = kwargs
+        .get("spectator_id")
+        .map(get_uuid)
+        .transpose()?
+        .map(PlayerId);
+    let invited = is_invited(&kwargs, &game_id)?;
+    let password = kwargs
+        .get("password")
+        .map(get_str)
+        .transpose()?
+        .map(str::to_string);
+
+    let result = STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            Ok(match authenticate(game, &player_id, &auth)? {
+                Some(PlayerType::Moderator) => game.serialize(true),
+                Some(PlayerType::Player) => game.serialize_for_player(&player_id),
+                Some(PlayerType::Waiting) => game.serialize(false),
+                None if spectator_id.is_some_and(|id| game.is_spectator(&id)) => {
+                    game.serialize(false)
+                }
+                None => {
+                    // Charged before the password is checked, so it can't be guessed quickly
+                    JOIN_LIMITER.check(game.id.clone())?;
+                    check_entry(game, invited, password.as_deref())?;
+                    game.serialize(false)
+                }
+            })
+        })
+        .await??;
 
-    let games = STATE
-        .games
-        .try_read_for(OPERATION_TIMEOUT)
-        .ok_or(Error::LockTimeout)?;
+    Ok((None, Some(result)))
+}
 
-    let game = games
-        .get(&game_id)
-        .ok_or(Error::UnknownGame)?
-        .try_read_for(OPERATION_TIMEOUT)
-        .ok_or(Error::LockTimeout)?;
+/// Moderator only: get the game's event log, optionally starting from a given index
+pub async fn get_event_log(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    debug!("get_event_log");
 
-    match game.auth_and_get_player_type(&player_id, &auth) {
-        Some(PlayerType::Moderator) => Ok((None, Some(game.serialize(true)))),
-        Some(PlayerType::Player) | None => Ok((None, Some(game.serialize(false)))),
-    }
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let since: usize = get_opt_str_parse(kwargs.get("since"))?.unwrap_or(0);
+
+    let events = STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if !matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(game
+                .get_events_since(since)
+                .iter()
+                .map(|event| WampPayloadValue::Object(event.serialize()))
+                .collect())
+        })
+        .await??;
+
+    let mut result = WampKwArgs::new();
+    result.insert("events".into(), WampPayloadValue::Array(events));
+
+    Ok((None, Some(result)))
 }
 
 /// Moderator only: end a game
@@ -240,39 +717,126 @@ pub async fn end_game(
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.is_ended = true;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.end();
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
-    MSG_QUEUE
-        .get()
-        .unwrap()
-        .send(Message {
-            topic: GAME_LOBBY_CHANNEL.into(),
-            args: None,
-            kwargs: Some(STATE.get_games()?),
+    Ok((None, None))
+}
+
+/// Moderator only: hide a game from the lobby and/or change its password
+pub async fn set_privacy(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("set_privacy");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let is_private: Option<bool> = get_opt_str_parse(kwargs.get("private"))?;
+    // An empty string removes the password; leaving the argument out keeps the current one.
+    let password = kwargs
+        .get("password")
+        .map(get_str)
+        .transpose()?
+        .map(str::to_string);
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                if let Some(is_private) = is_private {
+                    game.set_private(is_private);
+                }
+                if password.is_some() {
+                    game.set_password(password.as_deref());
+                }
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
         })
-        .unwrap();
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Moderator only: change the player cap and whether joins need approval
+pub async fn set_join_rules(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("set_join_rules");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let max_players = get_max_players(&kwargs)?;
+    let approve_joins: bool =
+        get_str_parse(kwargs.get("approve_joins").ok_or(Error::BadArgument)?)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.set_join_rules(max_players, approve_joins);
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Moderator only: accept or reject a pending join request
+pub async fn approve_join(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("approve_join");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let target = PlayerId(get_uuid(kwargs.get("target").ok_or(Error::BadArgument)?)?);
+    let approve: bool = get_str_parse(kwargs.get("approve").ok_or(Error::BadArgument)?)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.approve_join(&target, approve)?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
 }
 
@@ -285,31 +849,25 @@ pub async fn select_square(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let category: usize = get_str_parse(kwargs.get("category").ok_or(Error::BadArgument)?)?;
     let row: usize = get_str_parse(kwargs.get("row").ok_or(Error::BadArgument)?)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            let location = Location::new(category, row).ok_or(Error::InvalidSquare)?;
-            game.select_square(&location)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                let location = Location::new(category, row).ok_or(Error::InvalidSquare)?;
+                game.with_history(expected_version, |game| game.select_square(&location))?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -324,29 +882,23 @@ pub async fn answer(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let answer: AnswerType = get_str_parse(kwargs.get("answer").ok_or(Error::BadArgument)?)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.answer(answer)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| game.answer(answer))?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -361,6 +913,7 @@ pub async fn new_board(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let multiplier: i64 = get_str_parse(kwargs.get("multiplier").ok_or(Error::BadArgument)?)?;
     let daily_doubles: usize =
         get_str_parse(kwargs.get("daily_doubles").ok_or(Error::BadArgument)?)?;
@@ -370,8 +923,7 @@ pub async fn new_board(
     } else {
         Seed::new_random()
     };
-    let min_year = get_str_parse(kwargs.get("min_year").ok_or(Error::BadArgument)?)?;
-    let max_year = get_str_parse(kwargs.get("max_year").ok_or(Error::BadArgument)?)?;
+    let (min_year, max_year) = get_year_range(&kwargs)?;
 
     trace!(
         "new_board: multiplier: {}, daily doubles: {}, min_year: {}, max_year: {}, seed: {}",
@@ -382,34 +934,29 @@ pub async fn new_board(
         seed
     );
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.load_new_board(
-                multiplier,
-                daily_doubles,
-                categories,
-                min_year,
-                max_year,
-                seed,
-            )?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| {
+                    game.load_new_board(
+                        multiplier,
+                        daily_doubles,
+                        categories,
+                        min_year,
+                        max_year,
+                        seed,
+                    )
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -424,35 +971,30 @@ pub async fn start_final_jeopardy(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let seed: Seed = if let Some(WampPayloadValue::String(arg)) = kwargs.get("seed") {
         arg.parse().unwrap_or_else(|_| Seed::new_random())
     } else {
         Seed::new_random()
     };
-    let min_year = get_str_parse(kwargs.get("min_year").ok_or(Error::BadArgument)?)?;
-    let max_year = get_str_parse(kwargs.get("max_year").ok_or(Error::BadArgument)?)?;
+    let (min_year, max_year) = get_year_range(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| {
+                    game.start_final_jeopardy(seed, min_year, max_year)
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.start_final_jeopardy(seed, min_year, max_year)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -467,28 +1009,24 @@ pub async fn reveal_final_jeopardy_question(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| {
+                    game.reveal_final_jeopardy_question()
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.reveal_final_jeopardy_question()?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -503,28 +1041,22 @@ pub async fn lock_final_jeopardy_answers(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| game.lock_final_jeopardy_answers())?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.lock_final_jeopardy_answers()?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -539,31 +1071,59 @@ pub async fn reveal_final_jeopardy_info(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let target_id: PlayerId = PlayerId(get_uuid(kwargs.get("target").ok_or(Error::BadArgument)?)?);
     let info_type: FinalJeopardyInfoType =
         get_str_parse(kwargs.get("info_type").ok_or(Error::BadArgument)?)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.reveal_final_jeopardy_info(&target_id, info_type)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| {
+                    game.reveal_final_jeopardy_info(&target_id, info_type)
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Moderator only: reveal the next answer or wager in the final jeopardy sequence
+pub async fn reveal_next_final_jeopardy_info(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("reveal_next_final_jeopardy_info");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| {
+                    game.reveal_next_final_jeopardy_info()
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -578,30 +1138,26 @@ pub async fn evaluate_final_jeopardy_answer(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let target_id: PlayerId = PlayerId(get_uuid(kwargs.get("target").ok_or(Error::BadArgument)?)?);
     let answer: AnswerType = get_str_parse(kwargs.get("answer").ok_or(Error::BadArgument)?)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.evaluate_final_jeopardy_answer(&target_id, answer)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| {
+                    game.evaluate_final_jeopardy_answer(&target_id, answer)
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -616,40 +1172,36 @@ pub async fn change_square_state(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let category: usize = get_str_parse(kwargs.get("category").ok_or(Error::BadArgument)?)?;
     let row: usize = get_str_parse(kwargs.get("row").ok_or(Error::BadArgument)?)?;
     // true: ready; false: finished
     let new_state: bool = get_str_parse(kwargs.get("new_state").ok_or(Error::BadArgument)?)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            let location = Location::new(category, row).ok_or(Error::InvalidSquare)?;
-            game.set_square_state(
-                &location,
-                if new_state {
-                    SquareState::Normal
-                } else {
-                    SquareState::Finished
-                },
-            )?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                let location = Location::new(category, row).ok_or(Error::InvalidSquare)?;
+                game.with_history(expected_version, |game| {
+                    game.set_square_state(
+                        &location,
+                        if new_state {
+                            SquareState::Normal
+                        } else {
+                            SquareState::Finished
+                        },
+                    )
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -664,30 +1216,116 @@ pub async fn change_player_score(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
     let target = PlayerId(get_uuid(kwargs.get("target").ok_or(Error::BadArgument)?)?);
     let new_score: i64 = get_str_parse(kwargs.get("new_score").ok_or(Error::BadArgument)?)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.set_player_score(&target, new_score)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| {
+                    game.set_player_score(&target, new_score)
+                })?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Moderator only: roll back the last moderator action
+pub async fn undo(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("undo");
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.check_version(expected_version)?;
+                game.undo()?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Moderator only: re-apply the last moderator action that was undone
+pub async fn redo(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("redo");
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.check_version(expected_version)?;
+                game.redo()?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
+
+    STATE.broadcast_game_state_update(&game_id).await?;
+    Ok((None, None))
+}
+
+/// Moderator only: show the clue of a Daily Double once its wager is in
+pub async fn reveal_daily_double_clue(
+    _: Option<WampArgs>,
+    kwargs: Option<WampKwArgs>,
+) -> Result<(Option<WampArgs>, Option<WampKwArgs>), WampError> {
+    info!("reveal_daily_double_clue");
+
+    let kwargs = kwargs.ok_or(Error::BadArgument)?;
+    let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| game.reveal_daily_double_clue())?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -701,28 +1339,22 @@ pub async fn enable_buzzer(
     info!("enable_buzzer");
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
+    let expected_version = get_expected_version(&kwargs)?;
+
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Moderator)
+            ) {
+                game.with_history(expected_version, |game| game.enable_buzzer())?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Moderator)
-        ) {
-            game.enable_buzzer()?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -737,27 +1369,20 @@ pub async fn buzz(
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Player)
-        ) {
-            game.buzz(player_id)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Player)
+            ) {
+                game.buzz(player_id)?;
+            } else {
+                return Err(Error::NotAllowed);
+            }
+
+            Ok(())
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
     Ok((None, None))
@@ -773,30 +1398,24 @@ pub async fn submit_wager(
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
     let wager: i64 = get_str_parse(kwargs.get("wager").ok_or(Error::BadArgument)?)?;
 
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Player)
-        ) {
+    let submission = STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if !matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Player)
+            ) {
+                return Err(Error::NotAllowed);
+            }
+
             game.submit_wager(&player_id, wager)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+            Ok(game.get_final_jeopardy_submission(&player_id))
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
-    Ok((None, None))
+
+    // Echo back what was recorded, so the player knows it got through
+    Ok((None, submission))
 }
 
 pub async fn submit_final_jeopardy_answer(
@@ -807,30 +1426,54 @@ pub async fn submit_final_jeopardy_answer(
 
     let kwargs = kwargs.ok_or(Error::BadArgument)?;
     let (game_id, player_id, auth) = get_common_args(&kwargs)?;
-    let answer: &str = get_str(kwargs.get("answer").ok_or(Error::BadArgument)?)?;
-
-    {
-        let games = STATE
-            .games
-            .try_read_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        let mut game = games
-            .get(&game_id)
-            .ok_or(Error::UnknownGame)?
-            .try_write_for(OPERATION_TIMEOUT)
-            .ok_or(Error::LockTimeout)?;
-
-        if matches!(
-            game.auth_and_get_player_type(&player_id, &auth),
-            Some(PlayerType::Player)
-        ) {
-            game.submit_final_jeopardy_answer(&player_id, answer)?;
-        } else {
-            return Err(Error::NotAllowed.into());
-        }
-    }
+    let answer = get_str(kwargs.get("answer").ok_or(Error::BadArgument)?)?.to_string();
+
+    let submission = STATE
+        .with_game(&game_id, move |game| -> Result<_, Error> {
+            if !matches!(
+                authenticate(game, &player_id, &auth)?,
+                Some(PlayerType::Player)
+            ) {
+                return Err(Error::NotAllowed);
+            }
+
+            game.submit_final_jeopardy_answer(&player_id, &answer)?;
+            Ok(game.get_final_jeopardy_submission(&player_id))
+        })
+        .await??;
 
     STATE.broadcast_game_state_update(&game_id).await?;
-    Ok((None, None))
+
+    // Echo back what was recorded, so the player knows it got through
+    Ok((None, submission))
+}
+
+#[cfg(test)]
+mod server_tests {
+    use wamp_async::{WampError, WampKwArgs, WampPayloadValue};
+
+    use super::resolve_invite;
+    use crate::{config::Config, seed::Seed, InviteCode, CONFIG};
+
+    #[tokio::test]
+    async fn invite_lookups_are_rate_limited() {
+        let _ = CONFIG.set(Config::default());
+        let mut kwargs = WampKwArgs::new();
+        kwargs.insert(
+            "invite_code".into(),
+            WampPayloadValue::String(InviteCode(Seed::with_seed(1234)).to_string()),
+        );
+
+        let limit = CONFIG.get().unwrap().limits.invite_burst;
+        for _ in 0..limit {
+            match resolve_invite(None, Some(kwargs.clone())).await {
+                Err(WampError::UnknownError(code)) => assert_eq!(code, "jpdy.unknown_invite_code"),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        match resolve_invite(None, Some(kwargs)).await {
+            Err(WampError::UnknownError(code)) => assert_eq!(code, "jpdy.rate_limited"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -21,11 +21,13 @@ macro_rules! wamp_dict {
 macro_rules! rpc_register {
     ( $client:expr , { $( $name:expr => $fn:expr , )* } ) => {
         {
-            let results: Vec<std::pin::Pin<Box<dyn std::future::Future<Output = _>>>> = vec![
+            let results: Vec<std::pin::Pin<Box<dyn std::future::Future<Output = _> + Send>>> = vec![
                 $(
                     {
                         debug!("Registering {} to {}...", stringify!($fn), $name);
-                        Box::pin($client.register($name, $fn))
+                        Box::pin($client.register($name, |args, kwargs| {
+                            $crate::metrics::instrument($name, $fn(args, kwargs))
+                        }))
                     },
                 )*
             ];
@@ -99,6 +99,7 @@ export class JeopardyContextClass implements JeopardyContextType {
                     game_id: this.joinInfo.gameId,
                     player_id: EMPTY_GUID,
                     auth: EMPTY_GUID,
+                    spectator_id: this.joinInfo.spectatorId ?? EMPTY_GUID,
                 });
             }
         } else {
@@ -114,6 +115,7 @@ export interface GameJoinInfo {
     gameId: string,
     playerId: string | null,
     token: string | null,
+    spectatorId?: string, // only set for spectators
     channel: string,
 }
 
@@ -131,6 +133,7 @@ export enum Activity {
     // Moderator only
     Moderate,
     EnableBuzzer,
+    RevealDailyDoubleClue,
     EvaluateAnswer,
 }
 
@@ -140,7 +143,14 @@ export namespace ServerData {
         game_id: string;
         moderator: string;  // name
         players: string[];  // names
-        channel: string,
+    }
+
+    // What's published on the lobby channel. Every change to the list gets the next version.
+    export interface LobbyEvent {
+        event: 'game_added' | 'game_updated' | 'game_removed',
+        version: number,
+        game: OpenGame | undefined, // for game_added and game_updated
+        game_id: string | undefined, // for game_removed
     }
 
     export interface FinalJeopardyInfo {
@@ -158,6 +168,7 @@ export namespace ServerData {
     }
 
     export interface GameStateUpdate {
+        version: number,
         is_ended: boolean,
         players: { [player_id: string]: Player; },
         state: RemoteGameState,
@@ -167,6 +178,16 @@ export namespace ServerData {
         max_year: number,
     }
 
+    // What's published on the state channels: the fields of `GameStateUpdate` which changed since
+    // `base_version`, plus the board when it changed (null when it was taken away).
+    export interface GameStateDelta {
+        version: number,
+        base_version: number | null,
+        changed: Partial<GameStateUpdate>,
+        removed: string[],
+        board?: Board | null,
+    }
+
     export enum FinalJeopardyInfoType {
         Wager = 'Wager',
         Answer = 'Answer',
@@ -227,11 +248,15 @@ export namespace ServerData {
         controller: string | undefined,
     }
 
-    export interface WaitingForDailyDoubleWager {
-        type: 'WaitingForDailyDoubleWager',
+    export type DailyDoubleStage = 'Revealed' | 'WagerSubmitted' | 'ClueShown';
+
+    export interface DailyDouble {
+        type: 'DailyDouble',
         board: Board,
         controller: string,
         location: BoardLocation,
+        stage: DailyDoubleStage,
+        wager: string | undefined, // the moderator sees it once submitted, everyone once the clue is shown
     }
 
     export interface WaitingForEnableBuzzer {
@@ -269,7 +294,7 @@ export namespace ServerData {
     export type RemoteGameState =
         NoBoard |
         WaitingForSquareSelection |
-        WaitingForDailyDoubleWager |
+        DailyDouble |
         WaitingForEnableBuzzer |
         WaitingForBuzzer |
         WaitingForAnswer |
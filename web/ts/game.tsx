@@ -9,6 +9,9 @@ import { PlayersList } from './players';
 import { Toolbar } from './toolbar';
 import { FinalJeopardy } from './finalJeopardy';
 
+// Spectators who stop checking in are dropped by the server after a couple of minutes
+const SPECTATOR_HEARTBEAT_DELAY = 30 * 1000;
+
 interface GameState {
     isModerator: boolean,
     currentActivity: Activity,
@@ -17,6 +20,7 @@ interface GameState {
     controllerId: string | null,
     activePlayerId: string | null,
     moderatorName: string | null,
+    dailyDoubleWager: string | null,
 
     // Final Jeopardy stuff
     finalJeopardyCategory: string | null,
@@ -47,6 +51,7 @@ export class Game extends React.Component<GameProps, GameState> {
         controllerId: null,
         activePlayerId: null,
         moderatorName: null,
+        dailyDoubleWager: null,
         finalJeopardyCategory: null,
         finalJeopardyAirYear: null,
         finalJeopardyQuestion: null,
@@ -60,10 +65,19 @@ export class Game extends React.Component<GameProps, GameState> {
 
     private gameUpdateSubscription: autobahn.Subscription | null = null;
 
+    // The last full state, which deltas from the server are applied to
+    private remoteState: ServerData.GameStateUpdate | null = null;
+    private latestVersion = 0;
+    private fetchingState = false;
+    private spectatorHeartbeatId: number | null = null;
+
     constructor(props: GameProps) {
         super(props);
 
         this.loadNewState = this.loadNewState.bind(this);
+        this.applyDelta = this.applyDelta.bind(this);
+        this.fetchState = this.fetchState.bind(this);
+        this.keepSpectating = this.keepSpectating.bind(this);
         this.getEmptyBoard = this.getEmptyBoard.bind(this);
 
         this.leaveGameClicked = this.leaveGameClicked.bind(this);
@@ -112,7 +126,15 @@ export class Game extends React.Component<GameProps, GameState> {
                 case 'WaitingForSquareSelection': return Activity.Moderate;
                 case 'WaitingForEnableBuzzer': return Activity.EnableBuzzer;
                 case 'WaitingForBuzzer': return Activity.WaitForBuzz;
-                case 'WaitingForDailyDoubleWager': return Activity.WaitForDailyDoubleWager;
+                case 'DailyDouble': {
+                    if (gameState.stage === 'Revealed') {
+                        return Activity.WaitForDailyDoubleWager;
+                    } else if (gameState.stage === 'WagerSubmitted') {
+                        return Activity.RevealDailyDoubleClue;
+                    } else {
+                        return Activity.EvaluateAnswer;
+                    }
+                }
                 case 'WaitingForAnswer': return Activity.EvaluateAnswer;
                 case 'FinalJeopardy': return Activity.FinalJeopardy;
                 default: {
@@ -126,7 +148,9 @@ export class Game extends React.Component<GameProps, GameState> {
                 case 'WaitingForSquareSelection': return Activity.Wait;
                 case 'WaitingForEnableBuzzer': return Activity.Wait;
                 case 'WaitingForBuzzer': return Activity.Buzz;
-                case 'WaitingForDailyDoubleWager': return Activity.DailyDoubleWager;
+                case 'DailyDouble': {
+                    return gameState.stage === 'Revealed' ? Activity.DailyDoubleWager : Activity.WaitForEval;
+                }
                 case 'WaitingForAnswer': return Activity.WaitForEval;
                 case 'FinalJeopardy': return Activity.FinalJeopardy;
                 default: {
@@ -148,6 +172,8 @@ export class Game extends React.Component<GameProps, GameState> {
     getActivePlayer(gameState: ServerData.RemoteGameState): string | null {
         if (gameState.type === 'WaitingForAnswer') {
             return gameState.active_player;
+        } else if (gameState.type === 'DailyDouble' && gameState.stage === 'ClueShown') {
+            return gameState.controller;
         } else {
             return null;
         }
@@ -161,6 +187,66 @@ export class Game extends React.Component<GameProps, GameState> {
         }
     }
 
+    // Gets a full snapshot of the state, for when we join or miss an update.
+    fetchState() {
+        if (this.fetchingState) {
+            return;
+        }
+
+        this.context.withSession((session, argument) => {
+            this.fetchingState = true;
+            session.call<autobahn.Result>('jpdy.game_state', [], argument).then((result) => {
+                this.fetchingState = false;
+                const snapshot: ServerData.GameStateUpdate = result.kwargs;
+                this.remoteState = snapshot;
+                this.loadNewState(snapshot);
+
+                // Something changed while the snapshot was on its way
+                if (snapshot.version < this.latestVersion) {
+                    this.fetchState();
+                }
+            }, (error) => {
+                this.fetchingState = false;
+                handleError('game state call failed', error, true);
+            });
+        });
+    }
+
+    // Patches the last state with a delta from the state channel.
+    applyDelta(delta: ServerData.GameStateDelta) {
+        this.latestVersion = Math.max(this.latestVersion, delta.version);
+        if (this.remoteState === null || this.fetchingState) {
+            // fetchState will catch up once the snapshot arrives
+            return;
+        }
+        if (delta.version <= this.remoteState.version) {
+            return;
+        }
+        if (delta.base_version !== this.remoteState.version) {
+            this.fetchState();
+            return;
+        }
+
+        let newState: any = { ...this.remoteState, ...delta.changed };
+        for (const key of delta.removed) {
+            delete newState[key];
+        }
+
+        // The board is sent apart from the rest of the state, and only when it changes
+        const oldBoard = (this.remoteState.state as any).board;
+        if (delta.board === null) {
+            newState.state = { ...newState.state };
+            delete newState.state.board;
+        } else if (delta.board !== undefined) {
+            newState.state = { ...newState.state, board: delta.board };
+        } else if (oldBoard !== undefined) {
+            newState.state = { ...newState.state, board: oldBoard };
+        }
+
+        this.remoteState = newState;
+        this.loadNewState(newState);
+    }
+
     // Looks at the state update from the server and converts it to a new GameState.
     loadNewState(update: ServerData.GameStateUpdate) {
         if (update.is_ended) {
@@ -198,6 +284,7 @@ export class Game extends React.Component<GameProps, GameState> {
             controllerId: this.getController(update.state),
             activePlayerId: this.getActivePlayer(update.state),
             moderatorName: update.moderator,
+            dailyDoubleWager: (update.state.type === 'DailyDouble') ? (update.state.wager ?? null) : null,
             finalJeopardyCategory,
             finalJeopardyAirYear,
             finalJeopardyQuestion,
@@ -207,6 +294,16 @@ export class Game extends React.Component<GameProps, GameState> {
         });
     }
 
+    keepSpectating() {
+        this.context.withSession((session, argument) => {
+            session.call('jpdy.keep_spectating', [], argument).then(() => {
+                console.log('keep spectating call succeeded!');
+            }, (error) => {
+                handleError('no longer spectating the game', error, true);
+            });
+        });
+    }
+
     leaveGameClicked() {
         if (this.state.isModerator) {
             return;
@@ -223,6 +320,16 @@ export class Game extends React.Component<GameProps, GameState> {
                     handleError('leave game call failed', error, true);
                 });
             });
+        } else if (this.context.joinInfo?.spectatorId !== undefined) {
+            this.context.withSession((session, argument) => {
+                session.call('jpdy.stop_spectating', [], argument).then(() => {
+                    console.log('stop spectating call succeeded!');
+                }, (error) => {
+                    console.warn(`stop spectating call failed: ${JSON.stringify(error)}`);
+                }).then(() => {
+                    this.props.leaveGameCallback();
+                });
+            });
         } else {
             this.props.leaveGameCallback();
         }
@@ -264,27 +371,33 @@ export class Game extends React.Component<GameProps, GameState> {
     }
 
     componentDidMount() {
-        this.context.withSession((session, argument) => {
-            let initialState = session.call<autobahn.Result>('jpdy.game_state', [], argument);
-
-            let stateSubscription = session.subscribe(
+        this.context.withSession((session) => {
+            // Subscribe first, so nothing is missed between the snapshot and the first delta
+            session.subscribe(
                 this.context.joinInfo!.channel,
-                (_, update) => {
-                    this.loadNewState(update);
+                (_, delta) => {
+                    this.applyDelta(delta);
+                }).then((subscription) => {
+                    this.gameUpdateSubscription = subscription;
+                    this.fetchState();
+                }, (error) => {
+                    handleError('game subscription/setup failed', error, true);
                 });
-
-            Promise.all([
-                initialState, stateSubscription
-            ]).then(([initialState, subscription]) => {
-                this.gameUpdateSubscription = subscription;
-                this.loadNewState(initialState.kwargs);
-            }, (error) => {
-                handleError('game subscription/setup failed', error, true);
-            });
         });
+
+        // Check in right away, in case we're coming back to a game we were spectating before
+        if (this.context.joinInfo?.spectatorId !== undefined) {
+            this.keepSpectating();
+            this.spectatorHeartbeatId = window.setInterval(
+                this.keepSpectating, SPECTATOR_HEARTBEAT_DELAY);
+        }
     }
 
     componentWillUnmount() {
+        if (this.spectatorHeartbeatId !== null) {
+            window.clearInterval(this.spectatorHeartbeatId);
+            this.spectatorHeartbeatId = null;
+        }
         if (this.gameUpdateSubscription !== null && this.context.session !== null) {
             this.context.session.unsubscribe(this.gameUpdateSubscription);
             this.gameUpdateSubscription = null;
@@ -309,10 +422,10 @@ export class Game extends React.Component<GameProps, GameState> {
             this.context.fireEvent(EventNames.StopTimer);
         }
 
-        // If we're a moderator and the player submitted their daily double
-        // wager, start the timer.
+        // If we're a moderator and we revealed the daily double clue, start
+        // the timer.
         if (
-            (prevState.currentActivity === Activity.WaitForDailyDoubleWager) &&
+            (prevState.currentActivity === Activity.RevealDailyDoubleClue) &&
             (this.state.currentActivity === Activity.EvaluateAnswer)
         ) {
             this.context.fireEvent(EventNames.StartTimer);
@@ -391,6 +504,7 @@ export class Game extends React.Component<GameProps, GameState> {
                 activePlayer={activeName}
                 seed={this.state.board.seed}
                 players={this.state.players}
+                dailyDoubleWager={this.state.dailyDoubleWager}
                 finalJeopardyAnswersLocked={this.state.finalJeopardyAnswersLocked}
                 finalJeopardyQuestionRevealed={this.state.finalJeopardyQuestionRevealed}
                 finalJeopardySelectedPlayerId={this.state.finalJeopardySelectedPlayerId}
@@ -159,22 +159,36 @@ class Jeopardy extends React.Component<JeopardyProps, JeopardyState> {
     });
   }
 
-  spectateGame(gameId: string, channel: string) {
-    const joinInfo = {
-      channel,
-      gameId,
-      playerId: null,
-      token: null,
-    };
-    localStorage.setItem(LS_KEY_CUR_GAME, JSON.stringify(joinInfo));
+  spectateGame(gameId: string) {
+    if (this.state.session === null) {
+      console.warn('session is null when spectating game!');
+      return;
+    }
 
-    this.setState({
-      joinInfo,
-    });
+    // Spectating goes through the server, which hands out the channel once it has checked we're
+    // allowed to watch.
+    this.state.session.call<autobahn.Result>('jpdy.spectate', [], {
+      game_id: gameId,
+    }).then((result) => {
+      const joinInfo = {
+        channel: result.kwargs['channel'],
+        gameId,
+        playerId: null,
+        token: null,
+        spectatorId: result.kwargs['spectator_id'],
+      };
+      localStorage.setItem(LS_KEY_CUR_GAME, JSON.stringify(joinInfo));
 
-    ReactGA.event({
-      category: 'Navigation',
-      action: 'Spectated a game',
+      this.setState({
+        joinInfo,
+      });
+
+      ReactGA.event({
+        category: 'Navigation',
+        action: 'Spectated a game',
+      });
+    }, (error) => {
+      handleError('spectate game failed', error, true);
     });
   }
 
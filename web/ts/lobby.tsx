@@ -22,7 +22,7 @@ export interface LobbyProps {
     session: autobahn.Session,
     makeGameCallback: (name: string, avatar: string) => void,
     joinGameCallback: (name: string, avatar: string, gameId: string) => void,
-    spectateGameCallback: (gameId: string, channel: string) => void,
+    spectateGameCallback: (gameId: string) => void,
     gotGlobalMetadataCallback: (minCategoryYear: number, maxCategoryYear: number) => void,
 }
 
@@ -36,6 +36,11 @@ export class Lobby extends React.Component<LobbyProps, LobbyState> {
     };
 
     private subscription: autobahn.Subscription | null = null;
+
+    // The version of the list we have, which lobby events are applied to
+    private listVersion: number | null = null;
+    private latestVersion = 0;
+    private fetchingList = false;
     private userNameRef = React.createRef<HTMLInputElement>();
 
     constructor(props: LobbyProps) {
@@ -61,21 +66,61 @@ export class Lobby extends React.Component<LobbyProps, LobbyState> {
         });
     }
 
-    componentDidMount() {
+    // Gets the whole list, for when we start or miss an event.
+    fetchGameList() {
+        if (this.fetchingList) {
+            return;
+        }
+        this.fetchingList = true;
+
         this.props.session.call<autobahn.Result>('jpdy.list_games').then((result) => {
-            console.log('initial open games call succeeded!');
+            console.log('open games call succeeded!');
+            this.fetchingList = false;
+            this.listVersion = result.kwargs['version'];
             this.handleNewGameList(result.kwargs['games']);
             this.props.gotGlobalMetadataCallback(result.kwargs['min_year'], result.kwargs['max_year']);
+
+            // Something changed while the list was on its way
+            if (this.listVersion! < this.latestVersion) {
+                this.fetchGameList();
+            }
         }, (error: any) => {
-            handleError('initial open games request failed', error, false);
+            this.fetchingList = false;
+            handleError('open games request failed', error, false);
         });
+    }
+
+    handleLobbyEvent(event: ServerData.LobbyEvent) {
+        this.latestVersion = Math.max(this.latestVersion, event.version);
+        if (this.listVersion === null || this.fetchingList || this.state.openGames === null) {
+            // fetchGameList will catch up once the list arrives
+            return;
+        }
+        if (event.version <= this.listVersion) {
+            return;
+        }
+        if (event.version !== this.listVersion + 1) {
+            this.fetchGameList();
+            return;
+        }
+
+        this.listVersion = event.version;
+        let games = this.state.openGames.filter((game) =>
+            game.game_id !== (event.game?.game_id ?? event.game_id));
+        if (event.event !== 'game_removed' && event.game !== undefined) {
+            games.push(event.game);
+        }
+        this.handleNewGameList(games);
+    }
 
+    componentDidMount() {
+        // Subscribe first, so nothing is missed between the list and the first event
         this.props.session.subscribe(LOBBY_CHANNEL, (_, kwargs) => {
-            console.log('got new list of open games');
-            this.handleNewGameList(kwargs['games']);
-            this.props.gotGlobalMetadataCallback(kwargs['min_year'], kwargs['max_year']);
+            console.log(`got lobby event: ${kwargs['event']}`);
+            this.handleLobbyEvent(kwargs);
         }).then((subscription) => {
             this.subscription = subscription;
+            this.fetchGameList();
         }, (error: any) => {
             handleError('subscription to lobby channel failed', error, false);
         });
@@ -123,17 +168,8 @@ export class Lobby extends React.Component<LobbyProps, LobbyState> {
     // If gameId is null, we're making a new game.
     handleJoinOrMakeGameClick(gameId: string | null, joinAsSpectator: boolean) {
         if (joinAsSpectator) {
-            if (gameId !== null && this.state.openGames !== null) {
-                let channel = null;
-                for (let game of this.state.openGames) {
-                    if (game.game_id === gameId) {
-                        channel = game.channel;
-                    }
-                }
-
-                if (channel !== null) {
-                    this.props.spectateGameCallback(gameId, channel);
-                }
+            if (gameId !== null) {
+                this.props.spectateGameCallback(gameId);
             }
         } else {
             this.setState({
@@ -18,6 +18,7 @@ interface ControlsProps {
     seed: string | null,
     isBoardLoaded: boolean,
     players: { [playerId: string]: ServerData.Player },
+    dailyDoubleWager: string | null,
     finalJeopardyQuestionRevealed: boolean,
     finalJeopardyAnswersLocked: boolean,
     finalJeopardySelectedPlayerId: string | null,
@@ -251,6 +252,16 @@ export class ModeratorControls extends React.Component<ControlsProps, ModeratorC
         });
     }
 
+    revealDailyDoubleClueClicked() {
+        this.context.withSession((session, argument) => {
+            session.call('jpdy.reveal_daily_double_clue', [], argument).then(() => {
+                console.log('reveal daily double clue call succeeded!');
+            }, (error) => {
+                handleError('reveal daily double clue call failed', error, false);
+            });
+        });
+    }
+
     evalButtonClicked(answer: ServerData.AnswerType) {
         this.context.withSession((session, argument) => {
             argument['answer'] = answer;
@@ -382,6 +393,21 @@ export class ModeratorControls extends React.Component<ControlsProps, ModeratorC
                 break;
             }
 
+            case Activity.RevealDailyDoubleClue: {
+                activityString = `Read out the daily double wager ($${this.props.dailyDoubleWager}), ` +
+                    'then reveal the clue.';
+                for (let button of buttons.slice(0, 2)) {
+                    button.className = 'eval-button-reveal-question';
+                    button.string = 'Reveal Clue';
+                    button.enabled = true;
+                    button.handler = () => {
+                        this.revealDailyDoubleClueClicked();
+                    };
+                }
+                buttons[2].enabled = true;
+                break;
+            }
+
             case Activity.EvaluateAnswer: {
                 activityString = 'Wait for the active player to give an answer, then click ' +
                     'correct or incorrect.';